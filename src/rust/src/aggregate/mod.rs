@@ -0,0 +1,164 @@
+use crate::planner::nrow;
+use crate::progress::{install, SearchPool};
+use crate::string::joinmethod::JoinMethod;
+use crate::string::topk::{MatchCollector, MatchSink};
+use crate::utils::{any_numeric_to_vec64, robj_index_map};
+use anyhow::{anyhow, Result};
+use extendr_api::prelude::*;
+use rayon::prelude::*;
+
+/// Matches of one row of `df1`, summarized as they are found: how many,
+/// their smallest and total distance, and the 1-based row of `df2` of the
+/// closest one, the first in row order among ties.
+#[derive(Clone, Copy, Default)]
+pub struct MatchSummary {
+    pub count: usize,
+    pub min: f64,
+    pub sum: f64,
+    pub best: Option<usize>,
+}
+
+impl MatchSummary {
+    /// Count `n` matches at distance `dist`, of which the first in row order
+    /// is `row`.
+    pub fn add(&mut self, n: usize, dist: f64, row: usize) {
+        if self.best.is_none() || dist < self.min || (dist == self.min && Some(row) < self.best) {
+            self.min = dist;
+            self.best = Some(row);
+        }
+        self.count += n;
+        self.sum += dist * n as f64;
+    }
+}
+
+/// `df1` unchanged, plus the number of matches of each row in `n_matches`.
+/// With `distances`, also adds `min_distance`, `mean_distance` and
+/// `best_match`, the row of `df2` of the closest match (`NA` if none).
+pub fn summary_columns(df1: &List, summaries: &[MatchSummary], distances: bool) -> Result<List> {
+    let (mut names, mut values): (Vec<String>, Vec<Robj>) = df1
+        .iter()
+        .map(|(name, col)| (name.to_string(), col))
+        .unzip();
+
+    names.push("n_matches".to_string());
+    values.push(
+        summaries
+            .iter()
+            .map(|s| s.count as i32)
+            .collect::<Vec<_>>()
+            .into_robj(),
+    );
+
+    if distances {
+        let of_matched = |f: fn(&MatchSummary) -> f64| {
+            Doubles::from_values(summaries.iter().map(|s| {
+                if s.count > 0 {
+                    Rfloat::from(f(s))
+                } else {
+                    Rfloat::na()
+                }
+            }))
+            .into_robj()
+        };
+        names.push("min_distance".to_string());
+        values.push(of_matched(|s| s.min));
+        names.push("mean_distance".to_string());
+        values.push(of_matched(|s| s.sum / s.count as f64));
+        names.push("best_match".to_string());
+        values.push(
+            Integers::from_values(
+                summaries
+                    .iter()
+                    .map(|s| s.best.map_or(Rint::na(), |row| Rint::from(row as i32))),
+            )
+            .into_robj(),
+        );
+    }
+
+    List::from_names_and_values(names, values).map_err(|e| anyhow!("{e}"))
+}
+
+/// Summaries of the matches of each row of `df1` on one string column.
+/// Each distinct value of `df1` is searched once, and its matches are
+/// folded into a summary as soon as they are found, so no pairs are held in
+/// memory.
+pub fn string_summaries(
+    df1: &List,
+    left_key: &str,
+    df2: &List,
+    right_key: &str,
+    method: &JoinMethod,
+    pool: &SearchPool,
+) -> Result<Vec<MatchSummary>> {
+    let map1 = robj_index_map(df1, left_key)?;
+    let map2 = robj_index_map(df2, right_key)?;
+
+    let mut summaries = vec![MatchSummary::default(); nrow(df1)];
+    for (row, summary) in method.search(&map1, &map2, None, &Summarize, pool)? {
+        summaries[row - 1] = summary;
+    }
+    Ok(summaries)
+}
+
+// Folds the matches of each left value into the summary of each of its rows
+struct Summarize;
+
+impl MatchSink for Summarize {
+    type Item = (usize, MatchSummary);
+    type Out = Vec<(usize, MatchSummary)>;
+
+    fn take(&self, v1: &[usize], matches: MatchCollector) -> Self::Out {
+        let mut summary = MatchSummary::default();
+        for (row, dist) in matches.into_matches() {
+            summary.add(1, dist, row);
+        }
+        v1.iter().map(|&row| (row, summary)).collect()
+    }
+}
+
+/// Summaries of the matches of each row of `df1` within `max_distance` on
+/// one numeric column. The values of `df2` are sorted once, and the range
+/// within reach of each left value is scanned without storing any pairs.
+pub fn difference_summaries(
+    df1: &List,
+    left_key: &str,
+    df2: &List,
+    right_key: &str,
+    max_distance: f64,
+    pool: &SearchPool,
+) -> Result<Vec<MatchSummary>> {
+    let vec1 = any_numeric_to_vec64(df1, left_key)?;
+    let vec2 = any_numeric_to_vec64(df2, right_key)?;
+
+    let mut sorted: Vec<(f64, usize)> = vec2
+        .into_iter()
+        .enumerate()
+        .filter(|(_, y)| !y.is_nan())
+        .map(|(j, y)| (y, j + 1))
+        .collect();
+    sorted.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+
+    let threshold = max_distance + f64::EPSILON;
+    install(pool, vec1.len(), || {
+        vec1.par_iter()
+            .filter(|_| pool.tick())
+            .map(|&x| {
+                let mut summary = MatchSummary::default();
+                if x.is_nan() {
+                    return summary;
+                }
+                let start = sorted.partition_point(|&(y, _)| y < x - threshold);
+                for &(y, row) in sorted[start..]
+                    .iter()
+                    .take_while(|&&(y, _)| y <= x + threshold)
+                {
+                    let diff = (x - y).abs();
+                    if diff <= threshold {
+                        summary.add(1, diff, row);
+                    }
+                }
+                summary
+            })
+            .collect()
+    })
+}
@@ -0,0 +1,219 @@
+use crate::progress::SearchPool;
+use anyhow::{anyhow, Result};
+use ordered_float::OrderedFloat;
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Assignment {
+    Greedy,
+    Optimal,
+}
+
+impl Assignment {
+    /// Parse the `assignment` argument. `"none"` disables one-to-one matching.
+    pub fn new(s: &str) -> Result<Option<Self>> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(None),
+            "greedy" => Ok(Some(Assignment::Greedy)),
+            "optimal" => Ok(Some(Assignment::Optimal)),
+            _ => Err(anyhow!("Unknown `assignment` value: {}", s)),
+        }
+    }
+}
+
+pub fn find(parent: &mut [usize], mut x: usize) -> usize {
+    while parent[x] != x {
+        parent[x] = parent[parent[x]];
+        x = parent[x];
+    }
+    x
+}
+
+// Best-first: take pairs in order of increasing distance, skipping any pair
+// whose left or right row has already been assigned.
+fn greedy(idxs1: &[usize], idxs2: &[usize], scores: &[f64], positions: &mut [usize]) -> Vec<usize> {
+    positions.sort_by_key(|&p| (OrderedFloat(scores[p]), idxs1[p], idxs2[p]));
+    let mut used1 = FxHashSet::default();
+    let mut used2 = FxHashSet::default();
+    positions
+        .iter()
+        .filter(|&&p| {
+            if used1.contains(&idxs1[p]) || used2.contains(&idxs2[p]) {
+                return false;
+            }
+            used1.insert(idxs1[p]);
+            used2.insert(idxs2[p]);
+            true
+        })
+        .copied()
+        .collect()
+}
+
+// Residual graph of one connected component for min-cost flow: a source
+// (node 0) feeding each left row, an edge per candidate pair from its left to
+// its right row, and each right row draining into a sink. Edges are stored in
+// pairs, so that `e ^ 1` is the reverse of `e`.
+struct FlowGraph {
+    to: Vec<usize>,
+    cap: Vec<u8>,
+    cost: Vec<f64>,
+    adj: Vec<Vec<usize>>,
+}
+
+impl FlowGraph {
+    fn new(nodes: usize) -> Self {
+        FlowGraph {
+            to: Vec::new(),
+            cap: Vec::new(),
+            cost: Vec::new(),
+            adj: vec![Vec::new(); nodes],
+        }
+    }
+
+    fn add_edge(&mut self, from: usize, to: usize, cost: f64) -> usize {
+        let e = self.to.len();
+        self.to.extend([to, from]);
+        self.cap.extend([1, 0]);
+        self.cost.extend([cost, -cost]);
+        self.adj[from].push(e);
+        self.adj[to].push(e + 1);
+        e
+    }
+
+    // Push one unit along a shortest path from `source` to `sink` in reduced
+    // costs, then update the potentials. Returns false once no path is left.
+    fn augment(&mut self, source: usize, sink: usize, potential: &mut [f64]) -> bool {
+        let n = self.adj.len();
+        let mut dist = vec![f64::INFINITY; n];
+        let mut prev = vec![usize::MAX; n];
+        let mut heap = BinaryHeap::new();
+        dist[source] = 0.0;
+        heap.push((Reverse(OrderedFloat(0.0)), source));
+        while let Some((Reverse(OrderedFloat(d)), u)) = heap.pop() {
+            if d > dist[u] {
+                continue;
+            }
+            for &e in &self.adj[u] {
+                let v = self.to[e];
+                if self.cap[e] == 0 {
+                    continue;
+                }
+                // Rounding can leave tight edges slightly negative
+                let reduced = (self.cost[e] + potential[u] - potential[v]).max(0.0);
+                if d + reduced < dist[v] {
+                    dist[v] = d + reduced;
+                    prev[v] = e;
+                    heap.push((Reverse(OrderedFloat(dist[v])), v));
+                }
+            }
+        }
+        if dist[sink].is_infinite() {
+            return false;
+        }
+        for (p, d) in potential.iter_mut().zip(&dist) {
+            if d.is_finite() {
+                *p += d;
+            }
+        }
+        let mut v = sink;
+        while v != source {
+            let e = prev[v];
+            self.cap[e] -= 1;
+            self.cap[e ^ 1] += 1;
+            v = self.to[e ^ 1];
+        }
+        true
+    }
+}
+
+// Minimum-cost assignment of one connected component, as a min-cost flow
+// solved by successive shortest paths on the candidate pairs alone. Each path
+// adds one matched pair, so the solution first maximizes the number of
+// matched pairs and then minimizes their total distance. Memory grows with
+// the number of candidate pairs, not the product of the row counts.
+fn optimal(idxs1: &[usize], idxs2: &[usize], scores: &[f64], positions: &[usize]) -> Vec<usize> {
+    let mut rows: FxHashMap<usize, usize> = FxHashMap::default();
+    let mut cols: FxHashMap<usize, usize> = FxHashMap::default();
+    for &p in positions {
+        let nr = rows.len();
+        rows.entry(idxs1[p]).or_insert(nr);
+        let nc = cols.len();
+        cols.entry(idxs2[p]).or_insert(nc);
+    }
+    let (nr, nc) = (rows.len(), cols.len());
+    let (source, sink) = (0, nr + nc + 1);
+    let min = positions
+        .iter()
+        .map(|&p| scores[p])
+        .fold(f64::INFINITY, f64::min);
+
+    let mut graph = FlowGraph::new(nr + nc + 2);
+    for r in 0..nr {
+        graph.add_edge(source, 1 + r, 0.0);
+    }
+    let pair_edges: Vec<usize> = positions
+        .iter()
+        .map(|&p| {
+            let (r, c) = (rows[&idxs1[p]], cols[&idxs2[p]]);
+            graph.add_edge(1 + r, 1 + nr + c, scores[p] - min)
+        })
+        .collect();
+    for c in 0..nc {
+        graph.add_edge(1 + nr + c, sink, 0.0);
+    }
+
+    // Costs start non-negative, so zero potentials are valid
+    let mut potential = vec![0.0; nr + nc + 2];
+    while graph.augment(source, sink, &mut potential) {}
+
+    positions
+        .iter()
+        .zip(pair_edges)
+        .filter(|&(_, e)| graph.cap[e] == 0)
+        .map(|(&p, _)| p)
+        .collect()
+}
+
+/// Positions of the pairs kept by a one-to-one assignment, in ascending order.
+///
+/// Candidate pairs are split into connected components of the bipartite
+/// graph between left and right rows, and each component is solved on its
+/// own. Pairs are weighted by `scores`, where lower is better.
+pub fn assignment_positions(
+    idxs1: &[usize],
+    idxs2: &[usize],
+    scores: &[f64],
+    how: Assignment,
+    pool: &SearchPool,
+) -> Vec<usize> {
+    // Union-find over left rows (0..n1) and right rows (offset by n1)
+    let n1 = idxs1.iter().max().map_or(0, |&x| x + 1);
+    let n2 = idxs2.iter().max().map_or(0, |&x| x + 1);
+    let mut parent: Vec<usize> = (0..n1 + n2).collect();
+    for (&i, &j) in idxs1.iter().zip(idxs2) {
+        let (a, b) = (find(&mut parent, i), find(&mut parent, n1 + j));
+        if a != b {
+            parent[a] = b;
+        }
+    }
+    let mut components: FxHashMap<usize, Vec<usize>> = FxHashMap::default();
+    for (p, &i) in idxs1.iter().enumerate() {
+        let root = find(&mut parent, i);
+        components.entry(root).or_default().push(p);
+    }
+    let components: Vec<Vec<usize>> = components.into_values().collect();
+    let mut keep: Vec<usize> = pool.install(|| {
+        components
+            .into_par_iter()
+            .flat_map_iter(|mut positions| match how {
+                Assignment::Greedy => greedy(idxs1, idxs2, scores, &mut positions),
+                Assignment::Optimal => optimal(idxs1, idxs2, scores, &positions),
+            })
+            .collect()
+    });
+    keep.sort_unstable();
+    keep
+}
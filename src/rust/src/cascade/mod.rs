@@ -0,0 +1,104 @@
+use crate::merge::{dispatch_join, DistanceData, OutputSpec};
+use crate::progress::SearchPool;
+use crate::string::rule::StringRule;
+use crate::utils::{get_pool, summed_distances};
+use anyhow::{anyhow, Result};
+use extendr_api::prelude::*;
+
+/// Pairs matched by a cascade, with the pass that matched each pair.
+pub struct CascadeMatches {
+    pub idxs1: Vec<usize>,
+    pub idxs2: Vec<usize>,
+    pub passes: Vec<f64>,
+    pub dists: Vec<f64>,
+}
+
+/// Run the rules in order. Each pass only compares rows of either table that
+/// no earlier pass matched. Returns the matched pairs, the 1-based pass that
+/// matched each pair, and each pair's distance summed across that pass's keys.
+pub fn cascade_pairs(
+    df1: &List,
+    df2: &List,
+    rules: &[StringRule],
+    pool: &SearchPool,
+) -> Result<CascadeMatches> {
+    let n1 = df1.index(1).map_or(0, |col| col.len());
+    let n2 = df2.index(1).map_or(0, |col| col.len());
+    let mut matched1 = vec![false; n1 + 1];
+    let mut matched2 = vec![false; n2 + 1];
+
+    let mut out = CascadeMatches {
+        idxs1: Vec::new(),
+        idxs2: Vec::new(),
+        passes: Vec::new(),
+        dists: Vec::new(),
+    };
+
+    for (pass, rule) in rules.iter().enumerate() {
+        let rows1: Vec<usize> = (1..=n1).filter(|&i| !matched1[i]).collect();
+        let rows2: Vec<usize> = (1..=n2).filter(|&j| !matched2[j]).collect();
+        if rows1.is_empty() || rows2.is_empty() {
+            break;
+        }
+
+        // Unmatched rows form a single block for the first key
+        let (pass1, pass2, pass_dists) = rule.matches(df1, df2, Some(&[(rows1, rows2)]), pool)?;
+
+        for (&i, &j) in pass1.iter().zip(&pass2) {
+            matched1[i] = true;
+            matched2[j] = true;
+        }
+        out.passes
+            .resize(out.passes.len() + pass1.len(), (pass + 1) as f64);
+        out.dists.extend(summed_distances(&pass_dists));
+        out.idxs1.extend(pass1);
+        out.idxs2.extend(pass2);
+    }
+
+    Ok(out)
+}
+
+pub fn cascade_join(
+    df1: List,
+    df2: List,
+    rules: List,
+    how: String,
+    pass_col: String,
+    distance_col: Option<String>,
+    output: OutputSpec,
+    nthread: Option<usize>,
+) -> Result<List> {
+    let pool = get_pool(nthread)?;
+    let rules: Vec<StringRule> = rules
+        .values()
+        .enumerate()
+        .map(|(i, rule)| StringRule::new(&rule).map_err(|e| anyhow!("Rule {}: {e}", i + 1)))
+        .collect::<Result<_>>()?;
+    if rules.is_empty() {
+        return Err(anyhow!("At least one rule is required"));
+    }
+
+    let matches = cascade_pairs(&df1, &df2, &rules, &pool)?;
+
+    // The pass is always reported; the distance only when asked for
+    let mut columns = vec![(pass_col, matches.passes)];
+    if let Some(distance_col) = distance_col {
+        columns.push((distance_col, matches.dists));
+    }
+
+    // Output columns are named explicitly, so no join keys are needed
+    let by = List::new(0);
+    let out = dispatch_join(
+        how.as_str(),
+        &df1,
+        &df2,
+        matches.idxs1,
+        matches.idxs2,
+        None,
+        DistanceData::Named(&columns),
+        by,
+        &output,
+        &pool,
+    )?;
+    Ok(out)
+}
@@ -0,0 +1,357 @@
+pub mod rule;
+
+use crate::condition::rule::{DifferenceRule, DistanceRule, IntervalRule, RegexRule};
+use crate::merge::{dispatch_join, subset_and_label, DistanceData, OutputSpec};
+use crate::planner::{nrow, sample_rows};
+use crate::progress::SearchPool;
+use crate::string::rule::StringRule;
+use crate::string::slice_key;
+use crate::utils::get_pool;
+use anyhow::{anyhow, Result};
+use extendr_api::prelude::*;
+use rayon::prelude::*;
+
+/// Rows of each table used to estimate how many pairs each condition of an
+/// AND finds.
+const SAMPLE_ROWS: usize = 256;
+
+/// A join predicate: string, numeric, temporal, interval and regex rules
+/// combined with AND and OR.
+pub enum Condition {
+    String(StringRule),
+    Difference(DifferenceRule),
+    Distance(DistanceRule),
+    Interval(IntervalRule),
+    Regex(RegexRule),
+    And(Vec<Condition>),
+    Or(Vec<Condition>),
+}
+
+// Rows of the pairs at the given positions
+fn subset(positions: &[usize], idxs: &[usize]) -> Vec<usize> {
+    positions.iter().map(|&p| idxs[p]).collect()
+}
+
+// Positions of the pairs satisfying every one of `children`
+fn keep_all(
+    children: &[&Condition],
+    df1: &List,
+    idxs1: &[usize],
+    df2: &List,
+    idxs2: &[usize],
+    pool: &SearchPool,
+) -> Result<Vec<usize>> {
+    let mut keep: Vec<usize> = (0..idxs1.len()).collect();
+    for child in children {
+        if keep.is_empty() {
+            break;
+        }
+        let kept =
+            child.keep_positions(df1, &subset(&keep, idxs1), df2, &subset(&keep, idxs2), pool)?;
+        keep = subset(&kept, &keep);
+    }
+    Ok(keep)
+}
+
+impl Condition {
+    /// Parse a condition tree built by the R wrapper.
+    pub fn new(node: &Robj) -> Result<Self> {
+        let kind = node
+            .dollar("type")
+            .ok()
+            .and_then(|kind| kind.as_str().map(str::to_string))
+            .ok_or_else(|| anyhow!("Condition is missing its `type`"))?;
+
+        match kind.as_str() {
+            "string" => Ok(Condition::String(StringRule::new(node)?)),
+            "difference" => Ok(Condition::Difference(DifferenceRule::new(node)?)),
+            "distance" => Ok(Condition::Distance(DistanceRule::new(node)?)),
+            "interval" => Ok(Condition::Interval(IntervalRule::new(node)?)),
+            "regex" => Ok(Condition::Regex(RegexRule::new(node)?)),
+            "and" | "or" => {
+                let children = node
+                    .dollar("conditions")
+                    .ok()
+                    .and_then(|children| List::try_from(children).ok())
+                    .ok_or_else(|| anyhow!("`{}` condition has no list of conditions", kind))?;
+                let children: Vec<Condition> = children
+                    .values()
+                    .map(|child| Condition::new(&child))
+                    .collect::<Result<_>>()?;
+                if children.is_empty() {
+                    return Err(anyhow!("`{}` condition needs at least one condition", kind));
+                }
+                Ok(if kind == "and" {
+                    Condition::And(children)
+                } else {
+                    Condition::Or(children)
+                })
+            }
+            _ => Err(anyhow!("Unknown condition type `{}`", kind)),
+        }
+    }
+
+    /// All pairs satisfying the condition, sorted and without duplicates.
+    ///
+    /// A rule searches for its pairs directly. AND generates candidates from
+    /// the condition estimated to find the fewest pairs and checks the others
+    /// pairwise. OR takes the union of its conditions' pairs.
+    pub fn pairs(
+        &self,
+        df1: &List,
+        df2: &List,
+        pool: &SearchPool,
+    ) -> Result<(Vec<usize>, Vec<usize>)> {
+        match self {
+            Condition::String(rule) => {
+                let (idxs1, idxs2, _) = rule.matches(df1, df2, None, pool)?;
+                Ok((idxs1, idxs2))
+            }
+            Condition::Difference(rule) => rule.pairs(df1, df2, pool),
+            Condition::Distance(rule) => rule.pairs(df1, df2, pool),
+            Condition::Interval(rule) => rule.pairs(df1, df2, pool),
+            Condition::Regex(rule) => rule.pairs(df1, df2, pool),
+            Condition::And(children) => {
+                let (order, found) = plan(children, df1, df2, pool)?;
+                let (idxs1, idxs2) = match found {
+                    Some(pairs) => pairs,
+                    None => children[order[0]].pairs(df1, df2, pool)?,
+                };
+                let rest: Vec<&Condition> = order[1..].iter().map(|&c| &children[c]).collect();
+                let keep = keep_all(&rest, df1, &idxs1, df2, &idxs2, pool)?;
+                Ok((subset(&keep, &idxs1), subset(&keep, &idxs2)))
+            }
+            Condition::Or(children) => {
+                let mut pairs: Vec<(usize, usize)> = Vec::new();
+                for child in children {
+                    let (idxs1, idxs2) = child.pairs(df1, df2, pool)?;
+                    pairs.extend(idxs1.into_iter().zip(idxs2));
+                }
+                pairs.par_sort_unstable();
+                pairs.dedup();
+                Ok(pairs.into_iter().unzip())
+            }
+        }
+    }
+
+    /// Positions of the given pairs that satisfy the condition.
+    pub fn keep_positions(
+        &self,
+        df1: &List,
+        idxs1: &[usize],
+        df2: &List,
+        idxs2: &[usize],
+        pool: &SearchPool,
+    ) -> Result<Vec<usize>> {
+        match self {
+            Condition::String(rule) => rule.keep_positions(df1, idxs1, df2, idxs2, pool),
+            Condition::Difference(rule) => rule.keep_positions(df1, idxs1, df2, idxs2, pool),
+            Condition::Distance(rule) => rule.keep_positions(df1, idxs1, df2, idxs2, pool),
+            Condition::Interval(rule) => rule.keep_positions(df1, idxs1, df2, idxs2, pool),
+            Condition::Regex(rule) => rule.keep_positions(df1, idxs1, df2, idxs2, pool),
+            Condition::And(children) => {
+                let children: Vec<&Condition> = children.iter().collect();
+                keep_all(&children, df1, idxs1, df2, idxs2, pool)
+            }
+            Condition::Or(children) => {
+                // Only check each pair until one condition holds
+                let mut satisfied = vec![false; idxs1.len()];
+                for child in children {
+                    let rest: Vec<usize> = (0..idxs1.len()).filter(|&p| !satisfied[p]).collect();
+                    if rest.is_empty() {
+                        break;
+                    }
+                    let kept = child.keep_positions(
+                        df1,
+                        &subset(&rest, idxs1),
+                        df2,
+                        &subset(&rest, idxs2),
+                        pool,
+                    )?;
+                    for k in kept {
+                        satisfied[rest[k]] = true;
+                    }
+                }
+                Ok((0..idxs1.len()).filter(|&p| satisfied[p]).collect())
+            }
+        }
+    }
+
+    /// The rules of the condition, in the order they were given.
+    pub fn leaves(&self) -> Vec<&Condition> {
+        match self {
+            Condition::And(children) | Condition::Or(children) => {
+                children.iter().flat_map(|child| child.leaves()).collect()
+            }
+            leaf => vec![leaf],
+        }
+    }
+
+    /// Distance of every pair under a rule, one column per key named
+    /// `{left}_{right}_{method}`. Interval and regex rules have none.
+    fn distances(
+        &self,
+        df1: &List,
+        idxs1: &[usize],
+        df2: &List,
+        idxs2: &[usize],
+        pool: &SearchPool,
+    ) -> Result<Vec<(String, Vec<f64>)>> {
+        match self {
+            Condition::String(rule) => string_distances(rule, df1, idxs1, df2, idxs2, pool),
+            Condition::Difference(rule) => rule.distances(df1, idxs1, df2, idxs2, pool),
+            Condition::Distance(rule) => rule.distances(df1, idxs1, df2, idxs2, pool),
+            _ => Ok(Vec::new()),
+        }
+    }
+}
+
+// Rows of `df` evenly spaced across the table, or `None` if it has no more
+// than `SAMPLE_ROWS` rows
+fn sample_table(df: &List) -> Option<List> {
+    let n = nrow(df);
+    if n <= SAMPLE_ROWS {
+        return None;
+    }
+    let rows = sample_rows(n, SAMPLE_ROWS);
+    let (names, columns) = subset_and_label(df, &rows).ok()?;
+    List::from_names_and_values(names, columns).ok()
+}
+
+/// Order the conditions of an AND by how many pairs each finds between
+/// samples of `df1` and `df2`, fewest first, keeping the given order on ties.
+/// If neither table had to be sampled, the pairs found by the first
+/// condition are returned too.
+#[allow(clippy::type_complexity)]
+fn plan(
+    children: &[Condition],
+    df1: &List,
+    df2: &List,
+    pool: &SearchPool,
+) -> Result<(Vec<usize>, Option<(Vec<usize>, Vec<usize>)>)> {
+    if children.len() == 1 {
+        return Ok((vec![0], None));
+    }
+    let sample1 = sample_table(df1);
+    let sample2 = sample_table(df2);
+    let mut found: Vec<(Vec<usize>, Vec<usize>)> = children
+        .iter()
+        .map(|child| {
+            child.pairs(
+                sample1.as_ref().unwrap_or(df1),
+                sample2.as_ref().unwrap_or(df2),
+                pool,
+            )
+        })
+        .collect::<Result<_>>()?;
+
+    let mut order: Vec<usize> = (0..children.len()).collect();
+    order.sort_by_key(|&c| found[c].0.len());
+    let whole = sample1.is_none() && sample2.is_none();
+    let pairs = whole.then(|| found.swap_remove(order[0]));
+    Ok((order, pairs))
+}
+
+/// Distance of every pair on every key of a string rule, `NaN` where the
+/// pair cannot be compared.
+fn string_distances(
+    rule: &StringRule,
+    df1: &List,
+    idxs1: &[usize],
+    df2: &List,
+    idxs2: &[usize],
+    pool: &SearchPool,
+) -> Result<Vec<(String, Vec<f64>)>> {
+    let mut columns = Vec::new();
+    for (((lk, rk), join_method), label) in
+        rule.keys.iter().zip(&rule.join_methods).zip(&rule.labels)
+    {
+        let vec1_binding = slice_key(df1, lk, idxs1, "df1")?;
+        let vec1: Vec<&str> = vec1_binding
+            .as_str_vector()
+            .ok_or_else(|| anyhow!("Failed to convert df1 column '{}' to string vector", lk))?;
+        let vec2_binding = slice_key(df2, rk, idxs2, "df2")?;
+        let vec2: Vec<&str> = vec2_binding
+            .as_str_vector()
+            .ok_or_else(|| anyhow!("Failed to convert df2 column '{}' to string vector", rk))?;
+
+        let dist: Vec<f64> = pool.install(|| {
+            vec1.par_iter()
+                .zip(&vec2)
+                .map(|(a, b)| join_method.distance(a, b).unwrap_or(f64::NAN))
+                .collect()
+        });
+        columns.push((format!("{lk}_{rk}_{label}"), dist));
+    }
+    Ok(columns)
+}
+
+/// Distance of every pair under every rule, named `{distance_col}_{column}`.
+/// A column name used more than once gets a numeric suffix.
+pub fn condition_distances(
+    df1: &List,
+    idxs1: &[usize],
+    df2: &List,
+    idxs2: &[usize],
+    leaves: &[&Condition],
+    distance_col: &str,
+    pool: &SearchPool,
+) -> Result<Vec<(String, Vec<f64>)>> {
+    let mut columns: Vec<(String, Vec<f64>)> = Vec::new();
+    for leaf in leaves {
+        for (suffix, dist) in leaf.distances(df1, idxs1, df2, idxs2, pool)? {
+            let base = format!("{distance_col}_{suffix}");
+            let mut name = base.clone();
+            let mut n = 1;
+            while columns.iter().any(|(existing, _)| *existing == name) {
+                n += 1;
+                name = format!("{base}_{n}");
+            }
+            columns.push((name, dist));
+        }
+    }
+    Ok(columns)
+}
+
+pub fn condition_join(
+    df1: List,
+    df2: List,
+    condition: Robj,
+    how: String,
+    distance_col: Option<String>,
+    output: OutputSpec,
+    nthread: Option<usize>,
+) -> Result<List> {
+    let pool = get_pool(nthread)?;
+    let condition = Condition::new(&condition)?;
+
+    let (idxs1, idxs2) = condition.pairs(&df1, &df2, &pool)?;
+
+    let columns = match &distance_col {
+        Some(distance_col) => condition_distances(
+            &df1,
+            &idxs1,
+            &df2,
+            &idxs2,
+            &condition.leaves(),
+            distance_col,
+            &pool,
+        )?,
+        None => Vec::new(),
+    };
+
+    // Output columns are named explicitly, so no join keys are needed
+    let out = dispatch_join(
+        how.as_str(),
+        &df1,
+        &df2,
+        idxs1,
+        idxs2,
+        None,
+        DistanceData::Named(&columns),
+        List::new(0),
+        &output,
+        &pool,
+    )?;
+    Ok(out)
+}
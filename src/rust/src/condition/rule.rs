@@ -0,0 +1,324 @@
+use crate::difference::{difference_join, difference_positions};
+use crate::distance::{fuzzy_indices_dist, pair_distances, DistanceMetric};
+use crate::interval::real::{fuzzy_indices_interval_real, interval_positions};
+use crate::interval::OverlapType;
+use crate::progress::SearchPool;
+use crate::regex::{regex_join, regex_positions};
+use crate::string::extract_keys;
+use anyhow::{anyhow, Result};
+use extendr_api::prelude::*;
+
+fn field(node: &Robj, name: &str) -> Result<Robj> {
+    node.dollar(name)
+        .map_err(|_| anyhow!("Condition is missing `{}`", name))
+}
+
+fn by_field(node: &Robj) -> Result<(List, Vec<(String, String)>)> {
+    let by =
+        List::try_from(field(node, "by")?).map_err(|_| anyhow!("Condition `by` is not a list"))?;
+    let keys = extract_keys(&by)?;
+    Ok((by, keys))
+}
+
+fn real_field(node: &Robj, name: &str) -> Result<f64> {
+    field(node, name)?
+        .as_real()
+        .ok_or_else(|| anyhow!("Condition `{}` is not a number", name))
+}
+
+fn str_field(node: &Robj, name: &str) -> Result<String> {
+    field(node, name)?
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("Condition `{}` is not a string", name))
+}
+
+// Keep the pairs at the given positions
+fn keep_pairs(keep: &[usize], idxs1: &[usize], idxs2: &[usize]) -> (Vec<usize>, Vec<usize>) {
+    keep.iter().map(|&p| (idxs1[p], idxs2[p])).unzip()
+}
+
+// Order pairs by row, as string rules return them
+fn sorted_pairs(idxs1: Vec<usize>, idxs2: Vec<usize>) -> (Vec<usize>, Vec<usize>) {
+    let mut pairs: Vec<(usize, usize)> = idxs1.into_iter().zip(idxs2).collect();
+    pairs.sort_unstable();
+    pairs.into_iter().unzip()
+}
+
+/// Numeric or temporal columns that must each differ by at most
+/// `max_distance`.
+pub struct DifferenceRule {
+    pub keys: Vec<(String, String)>,
+    /// Labels distance columns, `"difference"` or `"temporal"`
+    pub label: String,
+    pub max_distance: f64,
+}
+
+impl DifferenceRule {
+    pub fn new(node: &Robj) -> Result<Self> {
+        let (_, keys) = by_field(node)?;
+        Ok(DifferenceRule {
+            keys,
+            label: str_field(node, "label")?,
+            max_distance: real_field(node, "max_distance")?,
+        })
+    }
+
+    pub fn pairs(
+        &self,
+        df1: &List,
+        df2: &List,
+        pool: &SearchPool,
+    ) -> Result<(Vec<usize>, Vec<usize>)> {
+        let (idxs1, idxs2, _) =
+            difference_join(df1, df2, self.keys[0].clone(), self.max_distance, pool)?;
+        let (idxs1, idxs2) = sorted_pairs(idxs1, idxs2);
+        let keep = self.keep_keys(&self.keys[1..], df1, &idxs1, df2, &idxs2, pool)?;
+        Ok(keep_pairs(&keep, &idxs1, &idxs2))
+    }
+
+    pub fn keep_positions(
+        &self,
+        df1: &List,
+        idxs1: &[usize],
+        df2: &List,
+        idxs2: &[usize],
+        pool: &SearchPool,
+    ) -> Result<Vec<usize>> {
+        self.keep_keys(&self.keys, df1, idxs1, df2, idxs2, pool)
+    }
+
+    fn keep_keys(
+        &self,
+        keys: &[(String, String)],
+        df1: &List,
+        idxs1: &[usize],
+        df2: &List,
+        idxs2: &[usize],
+        pool: &SearchPool,
+    ) -> Result<Vec<usize>> {
+        let mut keep: Vec<usize> = (0..idxs1.len()).collect();
+        for bypair in keys {
+            let (rows1, rows2) = keep_pairs(&keep, idxs1, idxs2);
+            let (kept, _) =
+                difference_positions(df1, &rows1, df2, &rows2, bypair, self.max_distance, pool)?;
+            keep = kept.iter().map(|&k| keep[k]).collect();
+        }
+        Ok(keep)
+    }
+
+    /// Absolute difference of every pair on each key.
+    pub fn distances(
+        &self,
+        df1: &List,
+        idxs1: &[usize],
+        df2: &List,
+        idxs2: &[usize],
+        pool: &SearchPool,
+    ) -> Result<Vec<(String, Vec<f64>)>> {
+        self.keys
+            .iter()
+            .map(|bypair| {
+                let (kept, diffs) =
+                    difference_positions(df1, idxs1, df2, idxs2, bypair, f64::INFINITY, pool)?;
+                let mut dist = vec![f64::NAN; idxs1.len()];
+                for (p, d) in kept.into_iter().zip(diffs) {
+                    dist[p] = d;
+                }
+                Ok((format!("{}_{}_{}", bypair.0, bypair.1, self.label), dist))
+            })
+            .collect()
+    }
+}
+
+/// Numeric columns whose rows must be within `max_distance` of each other
+/// as vectors.
+pub struct DistanceRule {
+    pub by: List,
+    pub keys: Vec<(String, String)>,
+    pub method: String,
+    pub metric: DistanceMetric,
+    pub max_distance: f64,
+}
+
+impl DistanceRule {
+    pub fn new(node: &Robj) -> Result<Self> {
+        let (by, keys) = by_field(node)?;
+        let method = str_field(node, "method")?;
+        Ok(DistanceRule {
+            by,
+            keys,
+            metric: DistanceMetric::new(&method)?,
+            method: method.to_lowercase(),
+            max_distance: real_field(node, "max_distance")?,
+        })
+    }
+
+    pub fn pairs(
+        &self,
+        df1: &List,
+        df2: &List,
+        pool: &SearchPool,
+    ) -> Result<(Vec<usize>, Vec<usize>)> {
+        let (idxs1, idxs2, _) =
+            fuzzy_indices_dist(df1, df2, &self.by, &self.method, self.max_distance, pool)?;
+        Ok(sorted_pairs(idxs1, idxs2))
+    }
+
+    pub fn keep_positions(
+        &self,
+        df1: &List,
+        idxs1: &[usize],
+        df2: &List,
+        idxs2: &[usize],
+        pool: &SearchPool,
+    ) -> Result<Vec<usize>> {
+        let dist = pair_distances(df1, idxs1, df2, idxs2, &self.keys, self.metric, pool)?;
+        Ok((0..dist.len())
+            .filter(|&p| dist[p] <= self.max_distance)
+            .collect())
+    }
+
+    /// Distance of every pair across all keys, as a single column.
+    pub fn distances(
+        &self,
+        df1: &List,
+        idxs1: &[usize],
+        df2: &List,
+        idxs2: &[usize],
+        pool: &SearchPool,
+    ) -> Result<Vec<(String, Vec<f64>)>> {
+        let dist = pair_distances(df1, idxs1, df2, idxs2, &self.keys, self.metric, pool)?;
+        let keys: Vec<String> = self
+            .keys
+            .iter()
+            .map(|(lk, rk)| format!("{lk}_{rk}"))
+            .collect();
+        Ok(vec![(format!("{}_{}", keys.join("_"), self.method), dist)])
+    }
+}
+
+/// Start and end columns whose intervals must overlap.
+pub struct IntervalRule {
+    pub by: List,
+    pub keys: Vec<(String, String)>,
+    pub overlap_type: String,
+    pub maxgap: f64,
+    pub minoverlap: f64,
+}
+
+impl IntervalRule {
+    pub fn new(node: &Robj) -> Result<Self> {
+        let (by, keys) = by_field(node)?;
+        if keys.len() != 2 {
+            return Err(anyhow!(
+                "Expected exactly two columns for interval matching (start and end)"
+            ));
+        }
+        let overlap_type = str_field(node, "overlap_type")?;
+        OverlapType::new(&overlap_type)?;
+        Ok(IntervalRule {
+            by,
+            keys,
+            overlap_type,
+            maxgap: real_field(node, "maxgap")?,
+            minoverlap: real_field(node, "minoverlap")?,
+        })
+    }
+
+    pub fn pairs(
+        &self,
+        df1: &List,
+        df2: &List,
+        pool: &SearchPool,
+    ) -> Result<(Vec<usize>, Vec<usize>)> {
+        let (idxs1, idxs2) = fuzzy_indices_interval_real(
+            df1,
+            df2,
+            &self.by,
+            &self.overlap_type,
+            self.maxgap,
+            self.minoverlap,
+            pool,
+        )?;
+        Ok(sorted_pairs(idxs1, idxs2))
+    }
+
+    pub fn keep_positions(
+        &self,
+        df1: &List,
+        idxs1: &[usize],
+        df2: &List,
+        idxs2: &[usize],
+        pool: &SearchPool,
+    ) -> Result<Vec<usize>> {
+        interval_positions(
+            df1,
+            idxs1,
+            df2,
+            idxs2,
+            &self.keys,
+            OverlapType::new(&self.overlap_type)?,
+            self.maxgap,
+            self.minoverlap,
+            pool,
+        )
+    }
+}
+
+/// Columns of `df1` that must match the regular expressions in `df2`.
+pub struct RegexRule {
+    pub keys: Vec<(String, String)>,
+    pub ignore_case: bool,
+}
+
+impl RegexRule {
+    pub fn new(node: &Robj) -> Result<Self> {
+        let (_, keys) = by_field(node)?;
+        let ignore_case = field(node, "ignore_case")?
+            .as_bool()
+            .ok_or_else(|| anyhow!("Condition `ignore_case` is not a logical"))?;
+        Ok(RegexRule { keys, ignore_case })
+    }
+
+    pub fn pairs(
+        &self,
+        df1: &List,
+        df2: &List,
+        pool: &SearchPool,
+    ) -> Result<(Vec<usize>, Vec<usize>)> {
+        let (idxs1, idxs2) = regex_join(df1, df2, self.keys[0].clone(), self.ignore_case, pool)?;
+        let (idxs1, idxs2) = sorted_pairs(idxs1, idxs2);
+        let keep = self.keep_keys(&self.keys[1..], df1, &idxs1, df2, &idxs2, pool)?;
+        Ok(keep_pairs(&keep, &idxs1, &idxs2))
+    }
+
+    pub fn keep_positions(
+        &self,
+        df1: &List,
+        idxs1: &[usize],
+        df2: &List,
+        idxs2: &[usize],
+        pool: &SearchPool,
+    ) -> Result<Vec<usize>> {
+        self.keep_keys(&self.keys, df1, idxs1, df2, idxs2, pool)
+    }
+
+    fn keep_keys(
+        &self,
+        keys: &[(String, String)],
+        df1: &List,
+        idxs1: &[usize],
+        df2: &List,
+        idxs2: &[usize],
+        pool: &SearchPool,
+    ) -> Result<Vec<usize>> {
+        let mut keep: Vec<usize> = (0..idxs1.len()).collect();
+        for bypair in keys {
+            let (rows1, rows2) = keep_pairs(&keep, idxs1, idxs2);
+            let kept = regex_positions(df1, &rows1, df2, &rows2, bypair, self.ignore_case, pool)?;
+            keep = kept.iter().map(|&k| keep[k]).collect();
+        }
+        Ok(keep)
+    }
+}
@@ -0,0 +1,110 @@
+use crate::assignment::find;
+use crate::merge::{dispatch_join, DistanceData, OutputSpec};
+use crate::string::{difference_pairs, extract_keys, key_join_methods, per_key_distances};
+use crate::utils::get_pool;
+use anyhow::{anyhow, Result};
+use extendr_api::prelude::*;
+
+/// Cluster id of every row, given the matched pairs of a self-join. Rows are
+/// clustered by the connected components of the match graph, so matches are
+/// treated as transitive. Ids are numbered from 1 in order of each cluster's
+/// first row, and unmatched rows form clusters of their own.
+pub fn cluster_ids(n: usize, idxs1: &[usize], idxs2: &[usize]) -> Vec<i32> {
+    let mut parent: Vec<usize> = (0..n).collect();
+    for (&i, &j) in idxs1.iter().zip(idxs2) {
+        let (a, b) = (find(&mut parent, i - 1), find(&mut parent, j - 1));
+        if a != b {
+            parent[a.max(b)] = a.min(b);
+        }
+    }
+
+    let mut ids = vec![0; n];
+    let mut next = 0;
+    for row in 0..n {
+        let root = find(&mut parent, row);
+        if root == row {
+            next += 1;
+            ids[row] = next;
+        } else {
+            ids[row] = ids[root];
+        }
+    }
+    ids
+}
+
+pub fn string_dedupe(
+    df: List,
+    by: List,
+    method: Vec<String>,
+    max_distance: Vec<f64>,
+    distance_col: Option<String>,
+    q: Vec<Rint>,
+    max_prefix: Vec<Rint>,
+    prefix_weight: Vec<Rfloat>,
+    clusters: bool,
+    cluster_col: String,
+    nthread: Option<usize>,
+) -> Result<List> {
+    let keys: Vec<(String, String)> = extract_keys(&by)?;
+    let pool = get_pool(nthread)?;
+
+    let join_methods = key_join_methods(
+        &keys,
+        &method,
+        &max_distance,
+        &q,
+        &max_prefix,
+        &prefix_weight,
+    )?;
+
+    let mut matchdat = join_methods[0].fuzzy_indices_self(&df, &keys[0].0, &pool)?;
+    matchdat.sort_unstable_by_key(|&(i, j, _)| (i, j));
+
+    let mut idxs1 = Vec::with_capacity(matchdat.len());
+    let mut idxs2 = Vec::with_capacity(matchdat.len());
+    let mut dists = Vec::with_capacity(matchdat.len());
+
+    for (i, j, d) in matchdat {
+        idxs1.push(i);
+        idxs2.push(j);
+        dists.push(d);
+    }
+    let mut dists = vec![dists];
+
+    for (bypair, join_method) in keys[1..].iter().zip(&join_methods[1..]) {
+        (idxs1, idxs2, dists) =
+            difference_pairs(&df, &idxs1, &df, &idxs2, bypair, &dists, join_method, &pool)?
+    }
+
+    // Either label each original row with its cluster, or return the pairs
+    if clusters {
+        let n = df.index(1).map_or(0, |col| col.len());
+        let ids = cluster_ids(n, &idxs1, &idxs2);
+
+        let (mut names, mut values): (Vec<String>, Vec<Robj>) =
+            df.iter().map(|(name, col)| (name.to_string(), col)).unzip();
+        names.push(cluster_col);
+        values.push(ids.into_robj());
+        return List::from_names_and_values(names, values).map_err(|e| anyhow!("{e}"));
+    }
+
+    let labels: Vec<String> = method.iter().map(|m| m.to_lowercase()).collect();
+    let dists = if keys.len() == 1 {
+        DistanceData::Single(&dists[0])
+    } else {
+        per_key_distances(&dists, &labels)
+    };
+    let out = dispatch_join(
+        "inner",
+        &df,
+        &df,
+        idxs1,
+        idxs2,
+        distance_col,
+        dists,
+        by,
+        &OutputSpec::default(),
+        &pool,
+    )?;
+    Ok(out)
+}
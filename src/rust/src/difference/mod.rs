@@ -1,17 +1,33 @@
+use crate::planner::{distinct_count, sample_pairs, selectivity};
+use crate::progress::{install, SearchPool};
 use crate::utils::any_numeric_to_vec64;
 use anyhow::{anyhow, Result};
 use core::f64;
 use extendr_api::prelude::*;
+use itertools::MultiUnzip;
 use rayon::prelude::*;
-use rayon::ThreadPool;
 use rustc_hash::FxHashMap;
 
 fn fuzzy_indices_diff(
     vec1: Vec<f64>,
     vec2: Vec<f64>,
     max_distance: f64,
-    pool: &ThreadPool,
-) -> (Vec<usize>, Vec<usize>, Vec<f64>) {
+    pool: &SearchPool,
+) -> Result<(Vec<usize>, Vec<usize>, Vec<f64>)> {
+    // Bucket the smaller side and probe it with the larger one, which also
+    // spreads the probes over more threads
+    if vec2.len() > vec1.len() {
+        let (idxs2, idxs1, dists) = fuzzy_indices_diff(vec2, vec1, max_distance, pool)?;
+        let mut triples: Vec<(usize, usize, f64)> = idxs1
+            .into_iter()
+            .zip(idxs2)
+            .zip(dists)
+            .map(|((i, j), d)| (i, j, d))
+            .collect();
+        triples.sort_by(|a, b| a.2.total_cmp(&b.2).then((a.0, a.1).cmp(&(b.0, b.1))));
+        return Ok(triples.into_iter().multiunzip());
+    }
+
     let indexed_vec1: Vec<(usize, f64)> = vec1.into_iter().enumerate().collect();
     let indexed_vec2: Vec<(usize, f64)> = vec2.into_iter().enumerate().collect();
 
@@ -27,17 +43,18 @@ fn fuzzy_indices_diff(
 
     let threshold = max_distance + f64::EPSILON;
 
-    pool.install(|| {
+    install(pool, indexed_vec1.len(), || {
         let mut lhs_indices = Vec::new();
         let mut rhs_indices = Vec::new();
         let mut distances = Vec::new();
 
         let mut triples = indexed_vec1
             .par_iter()
+            .filter(|_| pool.tick())
             .flat_map_iter(|&(i_idx, x)| {
                 let center = (x / bucket_width).floor() as i64;
 
-                [center - 1, center, center + 1]
+                let matches: Vec<_> = [center - 1, center, center + 1]
                     .into_iter()
                     .filter_map(|b| buckets.get(&b))
                     .flat_map(move |bucket| {
@@ -50,10 +67,13 @@ fn fuzzy_indices_diff(
                             }
                         })
                     })
+                    .collect();
+                pool.found(matches.len());
+                matches
             })
             .collect::<Vec<_>>();
 
-        triples.sort_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(std::cmp::Ordering::Equal));
+        triples.sort_by(|a, b| a.2.total_cmp(&b.2).then((a.0, a.1).cmp(&(b.0, b.1))));
         triples.into_iter().for_each(|(i, j, d)| {
             lhs_indices.push(i);
             rhs_indices.push(j);
@@ -69,7 +89,7 @@ pub fn difference_join(
     df2: &List,
     by: (String, String),
     max_distance: f64,
-    pool: &ThreadPool,
+    pool: &SearchPool,
 ) -> Result<(Vec<usize>, Vec<usize>, Vec<f64>)> {
     let lk = by.0.as_str();
     let rk = by.1.as_str();
@@ -77,20 +97,21 @@ pub fn difference_join(
     let vec1 = any_numeric_to_vec64(df1, lk)?;
     let vec2 = any_numeric_to_vec64(df2, rk)?;
 
-    let (idxs1, idxs2, dists) = fuzzy_indices_diff(vec1, vec2, max_distance, &pool);
+    let (idxs1, idxs2, dists) = fuzzy_indices_diff(vec1, vec2, max_distance, &pool)?;
     Ok((idxs1, idxs2, dists))
 }
 
-pub fn difference_pairs(
+/// Positions of the given pairs within `max_distance` on one key, with
+/// their absolute differences.
+pub fn difference_positions(
     df1: &List,
-    idxs1: &Vec<usize>,
+    idxs1: &[usize],
     df2: &List,
-    idxs2: &Vec<usize>,
+    idxs2: &[usize],
     by: &(String, String),
-    dists: &Vec<Vec<f64>>,
     max_distance: f64,
-    pool: &ThreadPool,
-) -> Result<(Vec<usize>, Vec<usize>, Vec<Vec<f64>>)> {
+    pool: &SearchPool,
+) -> Result<(Vec<usize>, Vec<f64>)> {
     let lk = by.0.as_str();
     let rk = by.1.as_str();
 
@@ -130,7 +151,7 @@ pub fn difference_pairs(
 
     let threshold = max_distance + f64::EPSILON;
 
-    let (idxs0, newdist): (Vec<usize>, Vec<f64>) = pool.install(|| {
+    let out = pool.install(|| {
         vec1.par_iter()
             .zip(vec2)
             .enumerate()
@@ -147,6 +168,40 @@ pub fn difference_pairs(
             })
             .unzip()
     });
+    Ok(out)
+}
+
+/// Estimated selectivity of each key, from a sample of pairs checked against
+/// `max_distance`.
+pub fn difference_selectivity(
+    df1: &List,
+    df2: &List,
+    keys: &[(String, String)],
+    max_distance: f64,
+    pool: &SearchPool,
+) -> Result<Vec<f64>> {
+    let (rows1, rows2) = sample_pairs(df1, df2);
+    keys.iter()
+        .map(|bypair| {
+            let (hits, _) =
+                difference_positions(df1, &rows1, df2, &rows2, bypair, max_distance, pool)?;
+            let distinct = distinct_count(df1, &bypair.0)?.max(distinct_count(df2, &bypair.1)?);
+            Ok(selectivity(hits.len(), rows1.len(), distinct))
+        })
+        .collect()
+}
+
+pub fn difference_pairs(
+    df1: &List,
+    idxs1: &[usize],
+    df2: &List,
+    idxs2: &[usize],
+    by: &(String, String),
+    dists: &Vec<Vec<f64>>,
+    max_distance: f64,
+    pool: &SearchPool,
+) -> Result<(Vec<usize>, Vec<usize>, Vec<Vec<f64>>)> {
+    let (idxs0, newdist) = difference_positions(df1, idxs1, df2, idxs2, by, max_distance, pool)?;
 
     let (idxs1b, idxs2b) = { idxs0.iter().map(|&i| (idxs1[i], idxs2[i])).unzip() };
 
@@ -1,9 +1,9 @@
+use crate::progress::{install, SearchPool};
 use anyhow::{anyhow, Result};
 use core::f64;
 use extendr_api::prelude::*;
 use itertools::MultiUnzip;
 use rayon::prelude::*;
-use rayon::ThreadPool;
 
 fn zip_columns(columns: &[Vec<f64>]) -> Vec<Vec<f64>> {
     let n_rows = columns[0].len();
@@ -26,7 +26,7 @@ pub fn fuzzy_indices_dist(
     by: &List,
     method: &str,
     max_distance: f64,
-    pool: &ThreadPool,
+    pool: &SearchPool,
 ) -> Result<(Vec<usize>, Vec<usize>, Vec<f64>)> {
     let keys: Vec<(String, String)> = by
         .iter()
@@ -82,6 +82,67 @@ impl DistanceMetric {
             _ => Err(anyhow!("Unknown distance metric: {}", s)),
         }
     }
+
+    pub fn distance(&self, a: &[f64], b: &[f64]) -> f64 {
+        match self {
+            DistanceMetric::Euclidean => a
+                .iter()
+                .zip(b.iter())
+                .map(|(x, y)| (x - y).powi(2))
+                .sum::<f64>()
+                .sqrt(),
+            DistanceMetric::Manhattan => a
+                .iter()
+                .zip(b.iter())
+                .map(|(x, y)| (x - y).abs())
+                .sum::<f64>(),
+        }
+    }
+}
+
+/// Distance between the rows of each given pair, across all keys.
+pub fn pair_distances(
+    df1: &List,
+    idxs1: &[usize],
+    df2: &List,
+    idxs2: &[usize],
+    keys: &[(String, String)],
+    metric: DistanceMetric,
+    pool: &SearchPool,
+) -> Result<Vec<f64>> {
+    let mut left_vecs = Vec::new();
+    let mut right_vecs = Vec::new();
+
+    for (left_key, right_key) in keys {
+        let leftvec = df1
+            .dollar(left_key)
+            .map_err(|_| anyhow!("Column '{}' not found in df1", left_key))?
+            .slice(idxs1)
+            .map_err(|_| anyhow!("Failed to slice df1 column '{}'", left_key))?
+            .as_real_vector()
+            .ok_or_else(|| anyhow!("Column '{}' in df1 is not numeric", left_key))?;
+        left_vecs.push(leftvec);
+
+        let rightvec = df2
+            .dollar(right_key)
+            .map_err(|_| anyhow!("Column '{}' not found in df2", right_key))?
+            .slice(idxs2)
+            .map_err(|_| anyhow!("Failed to slice df2 column '{}'", right_key))?
+            .as_real_vector()
+            .ok_or_else(|| anyhow!("Column '{}' in df2 is not numeric", right_key))?;
+        right_vecs.push(rightvec);
+    }
+
+    let left_rows = zip_columns(&left_vecs);
+    let right_rows = zip_columns(&right_vecs);
+
+    Ok(pool.install(|| {
+        left_rows
+            .par_iter()
+            .zip(&right_rows)
+            .map(|(a, b)| metric.distance(a, b))
+            .collect()
+    }))
 }
 
 pub fn filtered_distances(
@@ -89,42 +150,37 @@ pub fn filtered_distances(
     right: &[Vec<f64>],
     threshold: f64,
     metric: DistanceMetric,
-    pool: &rayon::ThreadPool,
+    pool: &SearchPool,
 ) -> anyhow::Result<(Vec<usize>, Vec<usize>, Vec<f64>)> {
-    pool.install(|| {
+    install(pool, left.len(), || {
         let results: Result<Vec<(usize, usize, f64)>> = left
             .par_iter()
             .enumerate()
+            .filter(|_| pool.tick())
             .flat_map_iter(|(i, a)| {
-                right.iter().enumerate().filter_map(move |(j, b)| {
-                    if a.len() != b.len() {
-                        return Some(Err(anyhow!(
-                            "Vector length mismatch at left[{}] and right[{}]",
-                            i,
-                            j
-                        )));
-                    }
-
-                    let dist = match metric {
-                        DistanceMetric::Euclidean => a
-                            .iter()
-                            .zip(b.iter())
-                            .map(|(x, y)| (x - y).powi(2))
-                            .sum::<f64>()
-                            .sqrt(),
-                        DistanceMetric::Manhattan => a
-                            .iter()
-                            .zip(b.iter())
-                            .map(|(x, y)| (x - y).abs())
-                            .sum::<f64>(),
-                    };
-
-                    if dist <= threshold {
-                        Some(Ok((i + 1, j + 1, dist)))
-                    } else {
-                        None
-                    }
-                })
+                let row: Vec<_> = right
+                    .iter()
+                    .enumerate()
+                    .filter_map(move |(j, b)| {
+                        if a.len() != b.len() {
+                            return Some(Err(anyhow!(
+                                "Vector length mismatch at left[{}] and right[{}]",
+                                i,
+                                j
+                            )));
+                        }
+
+                        let dist = metric.distance(a, b);
+
+                        if dist <= threshold {
+                            Some(Ok((i + 1, j + 1, dist)))
+                        } else {
+                            None
+                        }
+                    })
+                    .collect();
+                pool.found(row.len());
+                row
             })
             .collect();
 
@@ -136,5 +192,5 @@ pub fn filtered_distances(
             }
             Err(e) => Err(e),
         }
-    })
+    })?
 }
@@ -0,0 +1,162 @@
+use extendr_api::prelude::*;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+// Whether searches record busy time, set while an explained join runs
+static RECORDING: AtomicBool = AtomicBool::new(false);
+
+// Busy seconds and values searched by each worker thread
+static BUSY: Mutex<Vec<(f64, usize)>> = Mutex::new(Vec::new());
+
+// Pairs of values left to compare by the filter of the running search, for
+// filters that count them as they go
+static COMPARED: AtomicUsize = AtomicUsize::new(0);
+
+/// Times the search of one value on the current worker thread, if an
+/// explained join is running. Hold it for the duration of the search.
+pub struct BusyTimer {
+    start: Instant,
+}
+
+pub fn busy_timer() -> Option<BusyTimer> {
+    RECORDING.load(Ordering::Relaxed).then(|| BusyTimer {
+        start: Instant::now(),
+    })
+}
+
+/// Whether an explained join is running.
+pub fn recording() -> bool {
+    RECORDING.load(Ordering::Relaxed)
+}
+
+/// Count `n` pairs of values left to compare by the search's filter.
+pub fn compared(n: usize) {
+    COMPARED.fetch_add(n, Ordering::Relaxed);
+}
+
+/// Pairs of values counted with `compared()` since the join started.
+pub fn compared_count() -> usize {
+    COMPARED.load(Ordering::Relaxed)
+}
+
+impl Drop for BusyTimer {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let thread = rayon::current_thread_index().unwrap_or(0);
+        let mut busy = BUSY.lock().unwrap_or_else(|e| e.into_inner());
+        if busy.len() <= thread {
+            busy.resize(thread + 1, (0., 0));
+        }
+        busy[thread].0 += elapsed;
+        busy[thread].1 += 1;
+    }
+}
+
+/// Timings and counts of the stages of a join, returned as its explain
+/// report.
+pub struct Explain {
+    clock: Instant,
+    stages: Vec<(String, f64, Rfloat)>,
+    key_matches: Vec<(String, f64)>,
+    // Pairs of distinct values, and those left to compare by the filter
+    filter: Option<(String, f64, f64)>,
+    search_seconds: f64,
+}
+
+impl Explain {
+    pub fn start() -> Self {
+        BUSY.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        COMPARED.store(0, Ordering::Relaxed);
+        RECORDING.store(true, Ordering::Relaxed);
+        Explain {
+            clock: Instant::now(),
+            stages: Vec::new(),
+            key_matches: Vec::new(),
+            filter: None,
+            search_seconds: 0.,
+        }
+    }
+
+    /// Close a stage, timed from the end of the previous one, with the
+    /// number of pairs it left, if it produces pairs.
+    pub fn stage(&mut self, name: &str, pairs: Option<usize>) {
+        let seconds = self.clock.elapsed().as_secs_f64();
+        if name == "search" {
+            self.search_seconds = seconds;
+        }
+        let pairs = pairs.map_or(Rfloat::na(), |n| Rfloat::from(n as f64));
+        self.stages.push((name.to_string(), seconds, pairs));
+        self.clock = Instant::now();
+    }
+
+    /// Pairs left after checking a join column.
+    pub fn key_matches(&mut self, key: &str, pairs: usize) {
+        self.key_matches.push((key.to_string(), pairs as f64));
+    }
+
+    /// Pairs of distinct values the search could compare, and how many of
+    /// them the method's filter left to compare. Time spent counting them is
+    /// left out of the stages.
+    pub fn filter(&mut self, filter: &str, possible: usize, compared: usize) {
+        self.filter = Some((filter.to_string(), possible as f64, compared as f64));
+        self.clock = Instant::now();
+    }
+
+    pub fn finish(mut self, nthread: usize) -> List {
+        let busy = std::mem::take(&mut *BUSY.lock().unwrap_or_else(|e| e.into_inner()));
+
+        let (stage, (seconds, pairs)): (Vec<String>, (Vec<f64>, Vec<Rfloat>)) =
+            std::mem::take(&mut self.stages)
+                .into_iter()
+                .map(|(name, secs, pairs)| (name, (secs, pairs)))
+                .unzip();
+        let (key, key_pairs): (Vec<String>, Vec<f64>) =
+            std::mem::take(&mut self.key_matches).into_iter().unzip();
+        let filter = match self.filter.take() {
+            Some((name, possible, compared)) => list!(
+                filter = name,
+                possible = possible,
+                pruned = possible - compared,
+                compared = compared
+            ),
+            None => List::new(0),
+        };
+
+        let busy_seconds: Vec<f64> = busy.iter().map(|b| b.0).collect();
+        let values: Vec<f64> = busy.iter().map(|b| b.1 as f64).collect();
+        let capacity = nthread as f64 * self.search_seconds;
+        let utilization = if capacity > 0. {
+            busy_seconds.iter().sum::<f64>() / capacity
+        } else {
+            f64::NAN
+        };
+
+        list!(
+            stages = list!(stage = stage, seconds = seconds, pairs = pairs),
+            filter = filter,
+            matches_per_key = list!(key = key, pairs = key_pairs),
+            threads = list!(
+                thread = (1..=busy.len() as i32).collect::<Vec<i32>>(),
+                busy_seconds = busy_seconds,
+                values = values
+            ),
+            nthread = nthread as i32,
+            utilization = utilization
+        )
+    }
+}
+
+// Stop recording even if the join fails
+impl Drop for Explain {
+    fn drop(&mut self) {
+        RECORDING.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Close a stage of the report, if there is one.
+pub fn mark(explain: &mut Option<Explain>, name: &str, pairs: Option<usize>) {
+    if let Some(explain) = explain {
+        explain.stage(name, pairs);
+    }
+}
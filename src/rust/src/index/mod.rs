@@ -0,0 +1,324 @@
+use crate::merge::{dispatch_join, DistanceData, OutputSpec};
+use crate::planner::nrow;
+use crate::progress::SearchPool;
+use crate::string::extract_keys;
+use crate::string::joinmethod::{get_join_method, JoinMethod};
+use crate::string::topk::{MatchCollector, MatchSink};
+use crate::utils::{get_pool, robj_index_map};
+use anyhow::{anyhow, Result};
+use extendr_api::prelude::*;
+use itertools::{iproduct, MultiUnzip};
+use rustc_hash::FxHashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+// Marks files written by `StringIndex::save`, with the format version
+const MAGIC: &[u8; 8] = b"FOZZIDX1";
+
+/// Settings an index was built with. The method structures are derived from
+/// these, so they are all that is saved besides the values.
+pub struct IndexSettings {
+    pub key: String,
+    pub method: String,
+    pub max_distance: f64,
+    pub q: Option<usize>,
+    pub prefix_weight: Option<f64>,
+    pub max_prefix: Option<usize>,
+}
+
+impl IndexSettings {
+    fn join_method(&self) -> Result<JoinMethod> {
+        get_join_method(
+            &self.method,
+            self.max_distance,
+            self.q,
+            self.prefix_weight,
+            self.max_prefix,
+        )
+    }
+}
+
+/// Distinct values of one string column of a reference table, with the rows
+/// holding each value. Built once and searched by many joins with the string
+/// method it was built for. Missing values are left out, as they never
+/// match.
+pub struct StringIndex {
+    pub settings: IndexSettings,
+    join_method: JoinMethod,
+    /// Rows indexed so far, including rows with missing values
+    pub n_rows: usize,
+    values: Vec<String>,
+    rows: Vec<Vec<usize>>,
+    ids: FxHashMap<String, usize>,
+}
+
+impl StringIndex {
+    pub fn new(settings: IndexSettings) -> Result<Self> {
+        let join_method = settings.join_method()?;
+        Ok(StringIndex {
+            settings,
+            join_method,
+            n_rows: 0,
+            values: Vec::new(),
+            rows: Vec::new(),
+            ids: FxHashMap::default(),
+        })
+    }
+
+    /// Number of distinct values indexed.
+    pub fn n_values(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Index the rows of `df`, numbered after the rows already indexed.
+    pub fn append(&mut self, df: &List) -> Result<()> {
+        let key = &self.settings.key;
+        let col = df
+            .dollar(key)
+            .map_err(|_| anyhow!("Column {key} does not exist or is not string."))?;
+        let vals = col
+            .as_str_iter()
+            .ok_or_else(|| anyhow!("Column {key} does not exist or is not string."))?;
+
+        let mut added = 0;
+        for val in vals {
+            added += 1;
+            if val.is_na() {
+                continue;
+            }
+            let id = self.value_id(val);
+            self.rows[id].push(self.n_rows + added);
+        }
+        self.n_rows += added;
+        Ok(())
+    }
+
+    // Id of a value, adding it if it is new
+    fn value_id(&mut self, val: &str) -> usize {
+        if let Some(&id) = self.ids.get(val) {
+            return id;
+        }
+        let id = self.values.len();
+        self.values.push(val.to_string());
+        self.rows.push(Vec::new());
+        self.ids.insert(val.to_string(), id);
+        id
+    }
+
+    /// Pairs of 1-based rows of `df` and of the indexed table within the
+    /// index's `max_distance`, with their distance, ordered by row. The
+    /// distinct values of `df` are searched against the indexed values with
+    /// the string join's own search.
+    pub fn search(
+        &self,
+        df: &List,
+        key: &str,
+        pool: &SearchPool,
+    ) -> Result<Vec<(usize, usize, f64)>> {
+        let map1 = robj_index_map(df, key)?;
+        // Indexed values stand in for their rows until they are matched
+        let map2: FxHashMap<&str, Vec<usize>> = self
+            .values
+            .iter()
+            .enumerate()
+            .map(|(id, value)| (value.as_str(), vec![id]))
+            .collect();
+        let sink = IndexedRows { index: self, pool };
+        let mut idxs = self.join_method.search(&map1, &map2, None, &sink, pool)?;
+        idxs.sort_by_key(|&(i, j, _)| (i, j));
+        Ok(idxs)
+    }
+
+    /// Write the settings, values and rows to `path`. The search structure
+    /// is rebuilt from the values on load.
+    pub fn save(&self, path: &str) -> Result<()> {
+        let file = File::create(path).map_err(|e| anyhow!("Cannot write '{path}': {e}"))?;
+        let mut w = BufWriter::new(file);
+        w.write_all(MAGIC)?;
+
+        let s = &self.settings;
+        write_str(&mut w, &s.key)?;
+        write_str(&mut w, &s.method)?;
+        write_f64(&mut w, s.max_distance)?;
+        write_u64(&mut w, s.q.map_or(u64::MAX, |q| q as u64))?;
+        write_f64(&mut w, s.prefix_weight.unwrap_or(f64::NAN))?;
+        write_u64(&mut w, s.max_prefix.map_or(u64::MAX, |p| p as u64))?;
+
+        write_u64(&mut w, self.n_rows as u64)?;
+        write_u64(&mut w, self.values.len() as u64)?;
+        for (value, rows) in self.values.iter().zip(&self.rows) {
+            write_str(&mut w, value)?;
+            write_u64(&mut w, rows.len() as u64)?;
+            for &row in rows {
+                write_u64(&mut w, row as u64)?;
+            }
+        }
+        w.flush()?;
+        Ok(())
+    }
+
+    /// Read an index written by `save`.
+    pub fn load(path: &str) -> Result<Self> {
+        let file = File::open(path).map_err(|e| anyhow!("Cannot read '{path}': {e}"))?;
+        let mut r = BufReader::new(file);
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(anyhow!("'{path}' is not a fozziejoin index file"));
+        }
+
+        let settings = IndexSettings {
+            key: read_str(&mut r)?,
+            method: read_str(&mut r)?,
+            max_distance: read_f64(&mut r)?,
+            q: Some(read_u64(&mut r)?)
+                .filter(|&q| q != u64::MAX)
+                .map(|q| q as usize),
+            prefix_weight: Some(read_f64(&mut r)?).filter(|p| !p.is_nan()),
+            max_prefix: Some(read_u64(&mut r)?)
+                .filter(|&p| p != u64::MAX)
+                .map(|p| p as usize),
+        };
+        let mut index = StringIndex::new(settings)?;
+
+        index.n_rows = read_u64(&mut r)? as usize;
+        let n_values = read_u64(&mut r)?;
+        for _ in 0..n_values {
+            let value = read_str(&mut r)?;
+            let id = index.value_id(&value);
+            let n = read_u64(&mut r)?;
+            for _ in 0..n {
+                let row = read_u64(&mut r)? as usize;
+                index.rows[id].push(row);
+            }
+        }
+        Ok(index)
+    }
+}
+
+// Expands the matched value ids of each left value into pairs of rows,
+// counted towards `fozzie.max_matches`
+struct IndexedRows<'a> {
+    index: &'a StringIndex,
+    pool: &'a SearchPool,
+}
+
+impl MatchSink for IndexedRows<'_> {
+    type Item = (usize, usize, f64);
+    type Out = Vec<(usize, usize, f64)>;
+
+    fn take(&self, v1: &[usize], matches: MatchCollector) -> Self::Out {
+        let pairs: Vec<_> = matches
+            .into_matches()
+            .into_iter()
+            .flat_map(|(id, dist)| {
+                iproduct!(v1, &self.index.rows[id]).map(move |(&i, &j)| (i, j, dist))
+            })
+            .collect();
+        self.pool.found(pairs.len());
+        pairs
+    }
+}
+
+/// The index behind an external pointer. Pointers restored by `readRDS()`
+/// are null, as the index lives in memory only.
+pub fn index_ref(ptr: &ExternalPtr<StringIndex>) -> Result<&StringIndex> {
+    ptr.try_addr()
+        .map_err(|_| anyhow!("Index is no longer valid; save it with `fozzie_index_save()`"))
+}
+
+/// Mutable counterpart of `index_ref`.
+pub fn index_mut(ptr: &mut ExternalPtr<StringIndex>) -> Result<&mut StringIndex> {
+    ptr.try_addr_mut()
+        .map_err(|_| anyhow!("Index is no longer valid; save it with `fozzie_index_save()`"))
+}
+
+/// Join `df1` to the table `index` was built on, which is passed as `df2`
+/// to fill in its other columns.
+pub fn index_join(
+    df1: List,
+    df2: List,
+    index: &StringIndex,
+    by: List,
+    how: String,
+    distance_col: Option<String>,
+    output: OutputSpec,
+    nthread: Option<usize>,
+) -> Result<List> {
+    let keys = extract_keys(&by)?;
+    if keys.len() != 1 {
+        return Err(anyhow!("Index joins take exactly one join column"));
+    }
+    let (left_key, right_key) = &keys[0];
+    if *right_key != index.settings.key {
+        return Err(anyhow!(
+            "The index is on column '{}', not '{}'",
+            index.settings.key,
+            right_key
+        ));
+    }
+    if nrow(&df2) != index.n_rows {
+        return Err(anyhow!(
+            "`df2` has {} rows, but the index holds {}",
+            nrow(&df2),
+            index.n_rows
+        ));
+    }
+
+    let pool = get_pool(nthread)?;
+    let (idxs1, idxs2, dists): (Vec<usize>, Vec<usize>, Vec<f64>) = index
+        .search(&df1, left_key, &pool)?
+        .into_iter()
+        .multiunzip();
+
+    dispatch_join(
+        how.as_str(),
+        &df1,
+        &df2,
+        idxs1,
+        idxs2,
+        distance_col,
+        DistanceData::Single(&dists),
+        by,
+        &output,
+        &pool,
+    )
+}
+
+fn write_u64(w: &mut impl Write, x: u64) -> Result<()> {
+    w.write_all(&x.to_le_bytes())?;
+    Ok(())
+}
+
+fn write_f64(w: &mut impl Write, x: f64) -> Result<()> {
+    write_u64(w, x.to_bits())
+}
+
+fn write_str(w: &mut impl Write, s: &str) -> Result<()> {
+    write_u64(w, s.len() as u64)?;
+    w.write_all(s.as_bytes())?;
+    Ok(())
+}
+
+fn read_u64(r: &mut impl Read) -> Result<u64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)
+        .map_err(|_| anyhow!("Index file is truncated"))?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_f64(r: &mut impl Read) -> Result<f64> {
+    Ok(f64::from_bits(read_u64(r)?))
+}
+
+fn read_str(r: &mut impl Read) -> Result<String> {
+    let len = read_u64(r)?;
+    // The length is not trusted to size the buffer, so a corrupt file cannot
+    // make it allocate more than the file holds
+    let mut buf = Vec::new();
+    r.by_ref().take(len).read_to_end(&mut buf)?;
+    if buf.len() as u64 != len {
+        return Err(anyhow!("Index file is truncated"));
+    }
+    String::from_utf8(buf).map_err(|_| anyhow!("Index file holds invalid UTF-8"))
+}
@@ -1,19 +1,13 @@
 use crate::interval::OverlapType;
+use crate::progress::{install, SearchPool};
 use anyhow::{anyhow, Result};
 use extendr_api::prelude::*;
 use interavl::IntervalTree;
 use rayon::prelude::*;
-use rayon::ThreadPool;
 
-pub fn fuzzy_indices_interval_int(
-    df1: &List,
-    df2: &List,
-    by: &List,
-    overlap_type: &str,
-    maxgap: i32,
-    minoverlap: i32,
-    pool: &ThreadPool,
-) -> Result<(Vec<usize>, Vec<usize>)> {
+// Start and end columns of both sides, as `[left_start, left_end,
+// right_start, right_end]`, checked to hold valid intervals
+fn interval_columns(df1: &List, df2: &List, by: &List) -> Result<[Vec<i32>; 4]> {
     let keys: Vec<(String, String)> = by
         .iter()
         .map(|(left_key, val)| {
@@ -79,9 +73,92 @@ pub fn fuzzy_indices_interval_int(
         }
     }
 
+    Ok([left_start, left_end, right_start, right_end])
+}
+
+pub fn fuzzy_indices_interval_int(
+    df1: &List,
+    df2: &List,
+    by: &List,
+    overlap_type: &str,
+    maxgap: i32,
+    minoverlap: i32,
+    pool: &SearchPool,
+) -> Result<(Vec<usize>, Vec<usize>)> {
+    let [left_start, left_end, right_start, right_end] = interval_columns(df1, df2, by)?;
     let overlap_type = OverlapType::new(overlap_type)?;
 
-    // Build interval tree from df2
+    // Index the smaller side. Only "within" depends on which side is which.
+    let swap = right_start.len() > left_start.len() && !matches!(overlap_type, OverlapType::Within);
+    let results = if swap {
+        let mut results: Vec<(usize, usize)> = tree_pairs(
+            &right_start,
+            &right_end,
+            &left_start,
+            &left_end,
+            overlap_type,
+            maxgap,
+            minoverlap,
+            pool,
+        )?
+        .into_iter()
+        .map(|(j, i)| (i, j))
+        .collect();
+        results.sort_unstable();
+        results
+    } else {
+        tree_pairs(
+            &left_start,
+            &left_end,
+            &right_start,
+            &right_end,
+            overlap_type,
+            maxgap,
+            minoverlap,
+            pool,
+        )?
+    };
+
+    Ok((
+        results.iter().map(|(i, _)| *i).collect(),
+        results.iter().map(|(_, j)| *j).collect(),
+    ))
+}
+
+/// Number of rows of `df2` whose intervals match each row of `df1`, like
+/// `countOverlaps()`, without building the matched pairs.
+pub fn interval_counts_int(
+    df1: &List,
+    df2: &List,
+    by: &List,
+    overlap_type: &str,
+    maxgap: i32,
+    minoverlap: i32,
+    pool: &SearchPool,
+) -> Result<Vec<usize>> {
+    let [left_start, left_end, right_start, right_end] = interval_columns(df1, df2, by)?;
+    let overlap_type = OverlapType::new(overlap_type)?;
+    let tree = right_tree(&right_start, &right_end);
+
+    install(pool, left_start.len(), || {
+        left_start
+            .par_iter()
+            .zip(left_end.par_iter())
+            .filter(|_| pool.tick())
+            .map(|(&ls, &le)| {
+                let mut count = 0;
+                for_each_match(&tree, ls, le, overlap_type, maxgap, minoverlap, |jvec| {
+                    count += jvec.len()
+                });
+                count
+            })
+            .collect()
+    })
+}
+
+// Tree of the right intervals, with the 0-based rows of each distinct one.
+// Ends are inclusive, so each range runs to one past the end.
+fn right_tree(right_start: &[i32], right_end: &[i32]) -> IntervalTree<i32, Vec<usize>> {
     let mut tree: IntervalTree<i32, Vec<usize>> = IntervalTree::default();
     for (j, (&rs, &re)) in right_start.iter().zip(right_end.iter()).enumerate() {
         let rng = &(rs..(re + 1));
@@ -92,56 +169,81 @@ pub fn fuzzy_indices_interval_int(
             }
         }
     }
+    tree
+}
+
+// Call `f` with the rows of each distinct right interval matching `[ls, le]`
+fn for_each_match(
+    tree: &IntervalTree<i32, Vec<usize>>,
+    ls: i32,
+    le: i32,
+    overlap_type: OverlapType,
+    maxgap: i32,
+    minoverlap: i32,
+    mut f: impl FnMut(&Vec<usize>),
+) {
+    let query = (ls - maxgap - 1)..((maxgap + le) + 2);
+    for (range, jvec) in tree.iter_overlaps(&query) {
+        let rs = range.start;
+        let re = range.end - 1;
+
+        let gap = if le < rs {
+            rs - le - 1
+        } else if re < ls {
+            ls - re - 1
+        } else {
+            0
+        };
+
+        let overlap_len = (le.min(re) - ls.max(rs) + 1).max(0);
+
+        if gap > maxgap || overlap_len < minoverlap {
+            continue;
+        }
+
+        let semantic_match = match overlap_type {
+            OverlapType::Any => true,
+            OverlapType::Within => ls >= rs - maxgap && le <= re + maxgap,
+            OverlapType::Start => (ls - rs).abs() <= maxgap,
+            OverlapType::End => (le - re).abs() <= maxgap,
+        };
+
+        if semantic_match {
+            f(jvec);
+        }
+    }
+}
+
+// Sorted pairs of 1-based rows whose intervals match, searching a tree of
+// the right intervals for each left interval
+fn tree_pairs(
+    left_start: &[i32],
+    left_end: &[i32],
+    right_start: &[i32],
+    right_end: &[i32],
+    overlap_type: OverlapType,
+    maxgap: i32,
+    minoverlap: i32,
+    pool: &SearchPool,
+) -> Result<Vec<(usize, usize)>> {
+    let tree = right_tree(right_start, right_end);
 
-    pool.install(|| {
+    install(pool, left_start.len(), || {
         let mut results: Vec<(usize, usize)> = left_start
             .par_iter()
             .zip(left_end.par_iter())
             .enumerate()
+            .filter(|_| pool.tick())
             .flat_map_iter(|(i, (&ls, &le))| {
-                let query = (ls - maxgap - 1)..((maxgap + le) + 2);
-
-                tree.iter_overlaps(&query)
-                    .flat_map(move |(range, jvec)| {
-                        let mut idxs = vec![];
-                        let rs = range.start;
-                        let re = range.end - 1;
-
-                        let gap = if le < rs {
-                            rs - le - 1
-                        } else if re < ls {
-                            ls - re - 1
-                        } else {
-                            0
-                        };
-
-                        let overlap_len = (le.min(re) - ls.max(rs) + 1).max(0);
-
-                        if gap > maxgap || overlap_len < minoverlap {
-                            return None;
-                        }
-
-                        let semantic_match = match overlap_type {
-                            OverlapType::Any => true,
-                            OverlapType::Within => ls >= rs - maxgap && le <= re + maxgap,
-                            OverlapType::Start => (ls - rs).abs() <= maxgap,
-                            OverlapType::End => (le - re).abs() <= maxgap,
-                        };
-
-                        if semantic_match {
-                            jvec.iter().for_each(|j| idxs.push((i + 1, j + 1)));
-                        }
-                        Some(idxs)
-                    })
-                    .flatten()
-                    .collect::<Vec<_>>()
+                let mut idxs = vec![];
+                for_each_match(&tree, ls, le, overlap_type, maxgap, minoverlap, |jvec| {
+                    idxs.extend(jvec.iter().map(|j| (i + 1, j + 1)))
+                });
+                pool.found(idxs.len());
+                idxs
             })
             .collect();
         results.sort_unstable();
-
-        Ok((
-            results.iter().map(|(i, _)| *i).collect(),
-            results.iter().map(|(_, j)| *j).collect(),
-        ))
+        results
     })
 }
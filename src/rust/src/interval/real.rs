@@ -1,21 +1,102 @@
 use crate::interval::OverlapType;
+use crate::progress::{install, SearchPool};
 use crate::utils::any_numeric_to_vec64;
 use anyhow::{anyhow, Result};
 use extendr_api::prelude::*;
 use interavl::IntervalTree;
 use ordered_float::OrderedFloat;
 use rayon::prelude::*;
-use rayon::ThreadPool;
 
-pub fn fuzzy_indices_interval_real(
+/// Whether `[ls, le]` and `[rs, re]` match under the overlap type, gap and
+/// minimum overlap.
+pub fn overlaps(
+    ls: f64,
+    le: f64,
+    rs: f64,
+    re: f64,
+    overlap_type: OverlapType,
+    maxgap: f64,
+    minoverlap: f64,
+) -> bool {
+    let gap = if le < rs {
+        rs - le
+    } else if re < ls {
+        ls - re
+    } else {
+        0.0
+    };
+
+    let overlap_len = (le.min(re) - ls.max(rs)).max(0.0);
+
+    if gap > maxgap || overlap_len < minoverlap {
+        return false;
+    }
+
+    match overlap_type {
+        OverlapType::Any => true,
+        OverlapType::Within => ls >= rs - maxgap && le <= re + maxgap,
+        OverlapType::Start => (ls - rs).abs() <= maxgap,
+        OverlapType::End => (le - re).abs() <= maxgap,
+    }
+}
+
+/// Positions of the given pairs whose intervals match, with `keys` holding
+/// the start and end columns.
+pub fn interval_positions(
     df1: &List,
+    idxs1: &[usize],
     df2: &List,
-    by: &List,
-    overlap_type: &str,
+    idxs2: &[usize],
+    keys: &[(String, String)],
+    overlap_type: OverlapType,
     maxgap: f64,
     minoverlap: f64,
-    pool: &ThreadPool,
-) -> Result<(Vec<usize>, Vec<usize>)> {
+    pool: &SearchPool,
+) -> Result<Vec<usize>> {
+    let column = |df: &List, key: &str, idxs: &[usize], side: &str| -> Result<Vec<f64>> {
+        let col = df
+            .dollar(key)
+            .map_err(|_| anyhow!("Column `{}` not found in {}", key, side))?
+            .slice(idxs)
+            .map_err(|_| anyhow!("Failed to slice {} column `{}`", side, key))?;
+        if let Some(v) = col.as_real_vector() {
+            Ok(v)
+        } else if let Some(v) = col.as_integer_vector() {
+            Ok(v.iter().map(|&x| x as f64).collect())
+        } else {
+            Err(anyhow!(
+                "Column `{}` in {} is not numeric (integer or double)",
+                key,
+                side
+            ))
+        }
+    };
+    let left_start = column(df1, &keys[0].0, idxs1, "df1")?;
+    let left_end = column(df1, &keys[1].0, idxs1, "df1")?;
+    let right_start = column(df2, &keys[0].1, idxs2, "df2")?;
+    let right_end = column(df2, &keys[1].1, idxs2, "df2")?;
+
+    Ok(pool.install(|| {
+        (0..idxs1.len())
+            .into_par_iter()
+            .filter(|&p| {
+                overlaps(
+                    left_start[p],
+                    left_end[p],
+                    right_start[p],
+                    right_end[p],
+                    overlap_type,
+                    maxgap,
+                    minoverlap,
+                )
+            })
+            .collect()
+    }))
+}
+
+// Start and end columns of both sides, as `[left_start, left_end,
+// right_start, right_end]`, checked to hold valid intervals
+fn interval_columns(df1: &List, df2: &List, by: &List) -> Result<[Vec<f64>; 4]> {
     let keys: Vec<(String, String)> = by
         .iter()
         .map(|(left_key, val)| {
@@ -62,9 +143,94 @@ pub fn fuzzy_indices_interval_real(
         }
     }
 
+    Ok([left_start, left_end, right_start, right_end])
+}
+
+pub fn fuzzy_indices_interval_real(
+    df1: &List,
+    df2: &List,
+    by: &List,
+    overlap_type: &str,
+    maxgap: f64,
+    minoverlap: f64,
+    pool: &SearchPool,
+) -> Result<(Vec<usize>, Vec<usize>)> {
+    let [left_start, left_end, right_start, right_end] = interval_columns(df1, df2, by)?;
+    let overlap_type = OverlapType::new(overlap_type)?;
+
+    // Index the smaller side. Only "within" depends on which side is which.
+    let swap = right_start.len() > left_start.len() && !matches!(overlap_type, OverlapType::Within);
+    let results = if swap {
+        let mut results: Vec<(usize, usize)> = tree_pairs(
+            &right_start,
+            &right_end,
+            &left_start,
+            &left_end,
+            overlap_type,
+            maxgap,
+            minoverlap,
+            pool,
+        )?
+        .into_iter()
+        .map(|(j, i)| (i, j))
+        .collect();
+        results.sort_unstable();
+        results
+    } else {
+        tree_pairs(
+            &left_start,
+            &left_end,
+            &right_start,
+            &right_end,
+            overlap_type,
+            maxgap,
+            minoverlap,
+            pool,
+        )?
+    };
+
+    Ok((
+        results.iter().map(|(i, _)| *i).collect(),
+        results.iter().map(|(_, j)| *j).collect(),
+    ))
+}
+
+/// Number of rows of `df2` whose intervals match each row of `df1`, like
+/// `countOverlaps()`, without building the matched pairs.
+pub fn interval_counts_real(
+    df1: &List,
+    df2: &List,
+    by: &List,
+    overlap_type: &str,
+    maxgap: f64,
+    minoverlap: f64,
+    pool: &SearchPool,
+) -> Result<Vec<usize>> {
+    let [left_start, left_end, right_start, right_end] = interval_columns(df1, df2, by)?;
     let overlap_type = OverlapType::new(overlap_type)?;
+    let tree = right_tree(&right_start, &right_end);
+
+    install(pool, left_start.len(), || {
+        left_start
+            .par_iter()
+            .zip(left_end.par_iter())
+            .filter(|_| pool.tick())
+            .map(|(&ls, &le)| {
+                let mut count = 0;
+                for_each_match(&tree, ls, le, overlap_type, maxgap, minoverlap, |jvec| {
+                    count += jvec.len()
+                });
+                count
+            })
+            .collect()
+    })
+}
 
-    // Build interval tree from df2 using OrderedFloat
+// Tree of the right intervals, with the 0-based rows of each distinct one
+fn right_tree(
+    right_start: &[f64],
+    right_end: &[f64],
+) -> IntervalTree<OrderedFloat<f64>, Vec<usize>> {
     let mut tree: IntervalTree<OrderedFloat<f64>, Vec<usize>> = IntervalTree::default();
     for (j, (&rs, &re)) in right_start.iter().zip(right_end.iter()).enumerate() {
         let rng = &(OrderedFloat(rs)..OrderedFloat(re));
@@ -75,59 +241,64 @@ pub fn fuzzy_indices_interval_real(
             }
         }
     }
+    tree
+}
 
+// Call `f` with the rows of each distinct right interval matching `[ls, le]`
+fn for_each_match(
+    tree: &IntervalTree<OrderedFloat<f64>, Vec<usize>>,
+    ls: f64,
+    le: f64,
+    overlap_type: OverlapType,
+    maxgap: f64,
+    minoverlap: f64,
+    mut f: impl FnMut(&Vec<usize>),
+) {
     let epsilon = 1e-6;
-    pool.install(|| {
-        let results: Vec<(usize, usize)> = left_start
+    let query = OrderedFloat(ls - maxgap - epsilon)..OrderedFloat(le + maxgap + epsilon);
+    for (range, jvec) in tree.iter_overlaps(&query) {
+        if overlaps(
+            ls,
+            le,
+            range.start.0,
+            range.end.0,
+            overlap_type,
+            maxgap,
+            minoverlap,
+        ) {
+            f(jvec);
+        }
+    }
+}
+
+// Pairs of 1-based rows whose intervals match, searching a tree of the right
+// intervals for each left interval
+fn tree_pairs(
+    left_start: &[f64],
+    left_end: &[f64],
+    right_start: &[f64],
+    right_end: &[f64],
+    overlap_type: OverlapType,
+    maxgap: f64,
+    minoverlap: f64,
+    pool: &SearchPool,
+) -> Result<Vec<(usize, usize)>> {
+    let tree = right_tree(right_start, right_end);
+
+    install(pool, left_start.len(), || {
+        left_start
             .par_iter()
             .zip(left_end.par_iter())
             .enumerate()
+            .filter(|_| pool.tick())
             .flat_map_iter(|(i, (&ls, &le))| {
-                let query_start = OrderedFloat(ls - maxgap - epsilon);
-                let query_end = OrderedFloat(le + maxgap + epsilon);
-                let query = query_start..query_end;
-
-                tree.iter_overlaps(&query)
-                    .flat_map(move |(range, jvec)| {
-                        let mut idxs = vec![];
-
-                        let rs = range.start.0;
-                        let re = range.end.0;
-
-                        let gap = if le < rs {
-                            rs - le
-                        } else if re < ls {
-                            ls - re
-                        } else {
-                            0.0
-                        };
-
-                        let overlap_len = (le.min(re) - ls.max(rs)).max(0.0);
-
-                        if gap > maxgap || overlap_len < minoverlap {
-                            return None;
-                        }
-
-                        let semantic_match = match overlap_type {
-                            OverlapType::Any => true,
-                            OverlapType::Within => ls >= rs - maxgap && le <= re + maxgap,
-                            OverlapType::Start => (ls - rs).abs() <= maxgap,
-                            OverlapType::End => (le - re).abs() <= maxgap,
-                        };
-
-                        if semantic_match {
-                            jvec.iter().for_each(|j| idxs.push((i + 1, j + 1)));
-                        }
-                        Some(idxs)
-                    })
-                    .flatten()
-                    .collect::<Vec<_>>()
+                let mut idxs = vec![];
+                for_each_match(&tree, ls, le, overlap_type, maxgap, minoverlap, |jvec| {
+                    idxs.extend(jvec.iter().map(|j| (i + 1, j + 1)))
+                });
+                pool.found(idxs.len());
+                idxs
             })
-            .collect();
-
-        Ok((
-            results.iter().map(|(i, _)| *i).collect(),
-            results.iter().map(|(_, j)| *j).collect(),
-        ))
+            .collect()
     })
 }
@@ -2,23 +2,47 @@ use anyhow::{anyhow, Result};
 use core::f64;
 use extendr_api::prelude::*;
 
+pub mod aggregate;
+pub mod assignment;
+pub mod cascade;
+pub mod condition;
+pub mod dedupe;
 pub mod difference;
 pub mod distance;
+pub mod explain;
+pub mod index;
 pub mod interval;
+pub mod linkage;
 pub mod merge;
+pub mod pairs;
+pub mod planner;
+pub mod progress;
 pub mod regex;
+pub mod simhash;
 pub mod string;
 pub mod utils;
 
-use crate::difference::{difference_join, difference_pairs};
+use crate::aggregate::{difference_summaries, string_summaries, summary_columns, MatchSummary};
+use crate::assignment::{assignment_positions, Assignment};
+use crate::cascade::cascade_join;
+use crate::condition::condition_join;
+use crate::dedupe::string_dedupe;
+use crate::difference::{difference_join, difference_pairs, difference_selectivity};
 use crate::distance::fuzzy_indices_dist;
-use crate::interval::integer::fuzzy_indices_interval_int;
-use crate::interval::real::fuzzy_indices_interval_real;
+use crate::index::{index_join, index_mut, index_ref, IndexSettings, StringIndex};
+use crate::interval::integer::{fuzzy_indices_interval_int, interval_counts_int};
+use crate::interval::real::{fuzzy_indices_interval_real, interval_counts_real};
+use crate::linkage::linkage_join;
 use crate::merge::dispatch_join;
-use crate::merge::DistanceData;
-use crate::regex::{regex_join, regex_pairs};
-use crate::string::string_join;
-use crate::utils::get_pool;
+use crate::merge::{DistanceData, OutputSpec};
+use crate::pairs::{key_distances, pair_columns};
+use crate::planner::{key_order, restore_order};
+use crate::regex::{regex_join, regex_pairs, regex_selectivity};
+use crate::simhash::{simhash_join, SimHashFeatures};
+use crate::string::{
+    extract_keys, key_join_methods, key_selectivity, string_join, StringJoinSettings,
+};
+use crate::utils::{get_pool, subset_pairs, summed_distances};
 
 /// @title Internal: String Join via Rust
 /// @description Internal function. Performs a string-based fuzzy join using Rust backend.
@@ -29,29 +53,267 @@ pub fn fozzie_string_join_rs(
     df1: List,
     df2: List,
     by: List,
-    method: String,
     how: String,
-    max_distance: f64,
+    settings: List,
+    output: List,
+    nthread: Option<usize>,
+) -> Result<List> {
+    let settings = StringJoinSettings::from_list(&settings)?;
+    let output = OutputSpec::from_list(&output)?;
+    let result = string_join(df1, df2, by, how, settings, output, nthread)
+        .map_err(|e| anyhow!("Error in string join: {e}!"))?;
+    Ok(result)
+}
+
+/// @title Internal: String Deduplication via Rust
+/// @description Internal function. Finds fuzzy duplicate rows within a single data frame using Rust backend.
+/// @keywords internal
+/// @export
+#[extendr]
+pub fn fozzie_string_dedupe_rs(
+    df: List,
+    by: List,
+    method: Vec<String>,
+    max_distance: Vec<f64>,
     distance_col: Option<String>,
-    q: Option<i32>,
-    max_prefix: Option<i32>,
-    prefix_weight: Option<f64>,
+    q: Vec<Rint>,
+    max_prefix: Vec<Rint>,
+    prefix_weight: Vec<Rfloat>,
+    clusters: bool,
+    cluster_col: String,
+    nthread: Option<usize>,
+) -> Result<List> {
+    let result = string_dedupe(
+        df,
+        by,
+        method,
+        max_distance,
+        distance_col,
+        q,
+        max_prefix,
+        prefix_weight,
+        clusters,
+        cluster_col,
+        nthread,
+    )
+    .map_err(|e| anyhow!("Error in string dedupe: {e}!"))?;
+    Ok(result)
+}
+
+/// @title Internal: Cascade Join via Rust
+/// @description Internal function. Runs an ordered list of string join rules on the rows left unmatched by earlier rules using Rust backend.
+/// @keywords internal
+/// @export
+#[extendr]
+pub fn fozzie_cascade_join_rs(
+    df1: List,
+    df2: List,
+    rules: List,
+    how: String,
+    pass_col: String,
+    distance_col: Option<String>,
+    output: List,
+    nthread: Option<usize>,
+) -> Result<List> {
+    let output = OutputSpec::from_list(&output)?;
+    let result = cascade_join(
+        df1,
+        df2,
+        rules,
+        how,
+        pass_col,
+        distance_col,
+        output,
+        nthread,
+    )
+    .map_err(|e| anyhow!("Error in cascade join: {e}!"))?;
+    Ok(result)
+}
+
+/// @title Internal: Condition Join via Rust
+/// @description Internal function. Performs a join on AND/OR combinations of string conditions using Rust backend.
+/// @keywords internal
+/// @export
+#[extendr]
+pub fn fozzie_condition_join_rs(
+    df1: List,
+    df2: List,
+    condition: List,
+    how: String,
+    distance_col: Option<String>,
+    output: List,
     nthread: Option<usize>,
 ) -> Result<List> {
-    let result = string_join(
+    let output = OutputSpec::from_list(&output)?;
+    let result = condition_join(
+        df1,
+        df2,
+        condition.into(),
+        how,
+        distance_col,
+        output,
+        nthread,
+    )
+    .map_err(|e| anyhow!("Error in condition join: {e}!"))?;
+    Ok(result)
+}
+
+/// @title Internal: Probabilistic Linkage Join via Rust
+/// @description Internal function. Scores candidate pairs with a Fellegi-Sunter model fit by EM using Rust backend.
+/// @keywords internal
+/// @export
+#[extendr]
+pub fn fozzie_linkage_join_rs(
+    df1: List,
+    df2: List,
+    by: List,
+    method: Vec<String>,
+    how: String,
+    max_distance: Vec<f64>,
+    q: Vec<Rint>,
+    max_prefix: Vec<Rint>,
+    prefix_weight: Vec<Rfloat>,
+    min_probability: f64,
+    probability_col: String,
+    weight_col: String,
+    distance_col: Option<String>,
+    max_iter: i32,
+    output: List,
+    nthread: Option<usize>,
+) -> Result<List> {
+    let output = OutputSpec::from_list(&output)?;
+    if max_iter < 1 {
+        return Err(anyhow!("`max_iter` must be a positive integer"));
+    }
+    let result = linkage_join(
         df1,
         df2,
         by,
         method,
         how,
         max_distance,
-        distance_col,
         q,
         max_prefix,
         prefix_weight,
+        min_probability,
+        probability_col,
+        weight_col,
+        distance_col,
+        max_iter as usize,
+        output,
+        nthread,
+    )
+    .map_err(|e| anyhow!("Error in linkage join: {e}!"))?;
+    Ok(result)
+}
+
+/// @title Internal: Build String Index via Rust
+/// @description Internal function. Builds a reusable index over a string column of a reference table using Rust backend.
+/// @keywords internal
+/// @export
+#[extendr]
+pub fn fozzie_string_index_rs(
+    df: List,
+    key: String,
+    method: String,
+    max_distance: f64,
+    q: Rint,
+    max_prefix: Rint,
+    prefix_weight: Rfloat,
+) -> Result<ExternalPtr<StringIndex>> {
+    let settings = IndexSettings {
+        key,
+        method: method.to_lowercase(),
+        max_distance,
+        q: (!q.is_na()).then(|| q.inner() as usize),
+        prefix_weight: (!prefix_weight.is_na()).then(|| prefix_weight.inner()),
+        max_prefix: (!max_prefix.is_na()).then(|| max_prefix.inner() as usize),
+    };
+    let mut index =
+        StringIndex::new(settings).map_err(|e| anyhow!("Error in string index: {e}!"))?;
+    index
+        .append(&df)
+        .map_err(|e| anyhow!("Error in string index: {e}!"))?;
+    Ok(ExternalPtr::new(index))
+}
+
+/// @title Internal: Append to String Index via Rust
+/// @description Internal function. Adds reference rows to a string index using Rust backend.
+/// @keywords internal
+/// @export
+#[extendr]
+pub fn fozzie_index_append_rs(index: ExternalPtr<StringIndex>, df: List) -> Result<()> {
+    let mut index = index;
+    index_mut(&mut index)?
+        .append(&df)
+        .map_err(|e| anyhow!("Error in index append: {e}!"))
+}
+
+/// @title Internal: Save String Index via Rust
+/// @description Internal function. Writes a string index to disk using Rust backend.
+/// @keywords internal
+/// @export
+#[extendr]
+pub fn fozzie_index_save_rs(index: ExternalPtr<StringIndex>, path: String) -> Result<()> {
+    index_ref(&index)?
+        .save(&path)
+        .map_err(|e| anyhow!("Error in index save: {e}!"))
+}
+
+/// @title Internal: Load String Index via Rust
+/// @description Internal function. Reads a string index from disk using Rust backend.
+/// @keywords internal
+/// @export
+#[extendr]
+pub fn fozzie_index_load_rs(path: String) -> Result<ExternalPtr<StringIndex>> {
+    let index = StringIndex::load(&path).map_err(|e| anyhow!("Error in index load: {e}!"))?;
+    Ok(ExternalPtr::new(index))
+}
+
+/// @title Internal: String Index Summary via Rust
+/// @description Internal function. Describes a string index using Rust backend.
+/// @keywords internal
+/// @export
+#[extendr]
+pub fn fozzie_index_info_rs(index: ExternalPtr<StringIndex>) -> Result<List> {
+    let index = index_ref(&index)?;
+    let s = &index.settings;
+    Ok(list!(
+        column = s.key.as_str(),
+        method = s.method.as_str(),
+        max_distance = s.max_distance,
+        n_rows = index.n_rows as f64,
+        n_values = index.n_values() as f64
+    ))
+}
+
+/// @title Internal: Index Join via Rust
+/// @description Internal function. Joins a data frame to a prebuilt string index using Rust backend.
+/// @keywords internal
+/// @export
+#[extendr]
+pub fn fozzie_index_join_rs(
+    df1: List,
+    df2: List,
+    index: ExternalPtr<StringIndex>,
+    by: List,
+    how: String,
+    distance_col: Option<String>,
+    output: List,
+    nthread: Option<usize>,
+) -> Result<List> {
+    let output = OutputSpec::from_list(&output)?;
+    let result = index_join(
+        df1,
+        df2,
+        index_ref(&index)?,
+        by,
+        how,
+        distance_col,
+        output,
         nthread,
     )
-    .map_err(|e| anyhow!("Error in string join: {e}!"))?;
+    .map_err(|e| anyhow!("Error in index join: {e}!"))?;
     Ok(result)
 }
 
@@ -67,9 +329,13 @@ pub fn fozzie_difference_join_rs(
     how: String,
     max_distance: f64,
     distance_col: Option<String>,
+    assignment: String,
+    output: List,
     nthread: Option<usize>,
 ) -> Result<List> {
     let pool = get_pool(nthread)?;
+    let assignment = Assignment::new(&assignment)?;
+    let output = OutputSpec::from_list(&output)?;
 
     let keys: Vec<(String, String)> = by
         .iter()
@@ -81,52 +347,65 @@ pub fn fozzie_difference_join_rs(
         })
         .collect::<Result<_>>()?;
 
+    // The most selective key generates candidates
+    let order = if keys.len() > 1 {
+        key_order(&difference_selectivity(
+            &df1,
+            &df2,
+            &keys,
+            max_distance,
+            &pool,
+        )?)
+    } else {
+        vec![0]
+    };
+
     let (mut idxs1, mut idxs2, dists) =
-        difference_join(&df1, &df2, keys[0].clone(), max_distance, &pool)
+        difference_join(&df1, &df2, keys[order[0]].clone(), max_distance, &pool)
             .map_err(|e| anyhow!("Failed initial difference join: {}", e))?;
 
-    let out: List = if keys.len() == 1 {
-        let dists = DistanceData::Single(&dists);
-        dispatch_join(
-            how.as_str(),
+    let mut dists = vec![dists];
+    for bypair in order[1..].iter().map(|&k| &keys[k]) {
+        let (a, b, c) = difference_pairs(
             &df1,
+            &idxs1,
             &df2,
-            idxs1,
-            idxs2,
-            distance_col,
-            dists,
-            by,
+            &idxs2,
+            bypair,
+            &dists,
+            max_distance,
+            &pool,
         )
+        .map_err(|e| anyhow!("Failed difference_pairs for {:?}: {}", bypair, e))?;
+        idxs1 = a;
+        idxs2 = b;
+        dists = c;
+    }
+    let mut dists = restore_order(dists, &order);
+
+    if let Some(assignment) = assignment {
+        let scores = summed_distances(&dists);
+        let keep = assignment_positions(&idxs1, &idxs2, &scores, assignment, &pool);
+        (idxs1, idxs2, dists) = subset_pairs(&keep, &idxs1, &idxs2, &dists);
+    }
+
+    let dists = if keys.len() == 1 {
+        DistanceData::Single(&dists[0])
     } else {
-        let mut dists = vec![dists];
-        for bypair in &keys[1..] {
-            let (a, b, c) = difference_pairs(
-                &df1,
-                &idxs1,
-                &df2,
-                &idxs2,
-                bypair,
-                &dists,
-                max_distance,
-                &pool,
-            )
-            .map_err(|e| anyhow!("Failed difference_pairs for {:?}: {}", bypair, e))?;
-            idxs1 = a;
-            idxs2 = b;
-            dists = c;
-        }
-        let dists = DistanceData::Matrix(&dists);
-        dispatch_join(
-            how.as_str(),
-            &df1,
-            &df2,
-            idxs1,
-            idxs2,
-            distance_col,
-            dists,
-            by,
-        )
+        DistanceData::Matrix(&dists)
     };
+    let out = dispatch_join(
+        how.as_str(),
+        &df1,
+        &df2,
+        idxs1,
+        idxs2,
+        distance_col,
+        dists,
+        by,
+        &output,
+        &pool,
+    )?;
 
     Ok(out)
 }
@@ -144,12 +423,24 @@ pub fn fozzie_distance_join_rs(
     how: String,
     max_distance: f64,
     distance_col: Option<String>,
+    assignment: String,
+    output: List,
     nthread: Option<usize>,
 ) -> Result<List> {
     let pool = get_pool(nthread)?;
+    let assignment = Assignment::new(&assignment)?;
+    let output = OutputSpec::from_list(&output)?;
 
-    let (idxs1, idxs2, dists) = fuzzy_indices_dist(&df1, &df2, &by, &method, max_distance, &pool)
-        .map_err(|e| anyhow!("Error when finding fuzzy matches: {e}"))?;
+    let (mut idxs1, mut idxs2, mut dists) =
+        fuzzy_indices_dist(&df1, &df2, &by, &method, max_distance, &pool)
+            .map_err(|e| anyhow!("Error when finding fuzzy matches: {e}"))?;
+
+    if let Some(assignment) = assignment {
+        let keep = assignment_positions(&idxs1, &idxs2, &dists, assignment, &pool);
+        idxs1 = keep.iter().map(|&p| idxs1[p]).collect();
+        idxs2 = keep.iter().map(|&p| idxs2[p]).collect();
+        dists = keep.iter().map(|&p| dists[p]).collect();
+    }
     let dists = DistanceData::Single(&dists);
     let joined = dispatch_join(
         how.as_str(),
@@ -160,7 +451,9 @@ pub fn fozzie_distance_join_rs(
         distance_col,
         dists,
         by,
-    );
+        &output,
+        &pool,
+    )?;
     Ok(joined)
 }
 
@@ -178,8 +471,10 @@ pub fn fozzie_interval_join_rs(
     maxgap: f64,
     minoverlap: f64,
     interval_mode: &str,
+    output: List,
     nthread: Option<usize>,
 ) -> Result<List> {
+    let output = OutputSpec::from_list(&output)?;
     let pool = get_pool(nthread)?;
 
     let (idxs1, idxs2) = match interval_mode {
@@ -201,7 +496,18 @@ pub fn fozzie_interval_join_rs(
     let empty = vec![];
     let dists = DistanceData::Single(&empty);
 
-    let joined = dispatch_join(how.as_str(), &df1, &df2, idxs1, idxs2, None, dists, by);
+    let joined = dispatch_join(
+        how.as_str(),
+        &df1,
+        &df2,
+        idxs1,
+        idxs2,
+        None,
+        dists,
+        by,
+        &output,
+        &pool,
+    )?;
     Ok(joined)
 }
 
@@ -216,8 +522,10 @@ pub fn fozzie_regex_join_rs(
     by: List,
     how: String,
     ignore_case: bool,
+    output: List,
     nthread: Option<usize>,
 ) -> Result<List> {
+    let output = OutputSpec::from_list(&output)?;
     let pool = get_pool(nthread)?;
 
     let keys: Vec<(String, String)> = by
@@ -230,15 +538,33 @@ pub fn fozzie_regex_join_rs(
         })
         .collect::<Result<_>>()?;
 
-    let (mut idxs1, mut idxs2) = regex_join(&df1, &df2, keys[0].clone(), ignore_case, &pool)
+    // The most selective key generates candidates
+    let order = if keys.len() > 1 {
+        key_order(&regex_selectivity(&df1, &df2, &keys, ignore_case, &pool)?)
+    } else {
+        vec![0]
+    };
+
+    let (mut idxs1, mut idxs2) = regex_join(&df1, &df2, keys[order[0]].clone(), ignore_case, &pool)
         .map_err(|e| anyhow!("Failed initial regex join: {}", e))?;
 
     let out: List = if keys.len() == 1 {
         let dists: Vec<f64> = Vec::new();
         let dists = DistanceData::Single(&dists);
-        dispatch_join(how.as_str(), &df1, &df2, idxs1, idxs2, None, dists, by)
+        dispatch_join(
+            how.as_str(),
+            &df1,
+            &df2,
+            idxs1,
+            idxs2,
+            None,
+            dists,
+            by,
+            &output,
+            &pool,
+        )?
     } else {
-        for bypair in &keys[1..] {
+        for bypair in order[1..].iter().map(|&k| &keys[k]) {
             let (a, b) = regex_pairs(&df1, &idxs1, &df2, &idxs2, bypair, ignore_case, &pool)
                 .map_err(|e| anyhow!("Failed difference_pairs for {:?}: {}", bypair, e))?;
             idxs1 = a;
@@ -246,12 +572,378 @@ pub fn fozzie_regex_join_rs(
         }
         let dists: Vec<Vec<f64>> = Vec::new();
         let dists = DistanceData::Matrix(&dists);
-        dispatch_join(how.as_str(), &df1, &df2, idxs1, idxs2, None, dists, by)
+        dispatch_join(
+            how.as_str(),
+            &df1,
+            &df2,
+            idxs1,
+            idxs2,
+            None,
+            dists,
+            by,
+            &output,
+            &pool,
+        )?
     };
 
     Ok(out)
 }
 
+/// @title Internal: SimHash Join via Rust
+/// @description Internal function. Performs a SimHash-based near-duplicate join using Rust backend.
+/// @keywords internal
+/// @export
+#[extendr]
+pub fn fozzie_simhash_join_rs(
+    df1: List,
+    df2: List,
+    by: List,
+    how: String,
+    features: String,
+    max_distance: f64,
+    distance_col: Option<String>,
+    q: Option<i32>,
+    output: List,
+    nthread: Option<usize>,
+) -> Result<List> {
+    let output = OutputSpec::from_list(&output)?;
+    let pool = get_pool(nthread)?;
+
+    let keys: Vec<(String, String)> = by
+        .iter()
+        .map(|(left_key, val)| {
+            let right_key = val
+                .as_string_vector()
+                .ok_or_else(|| anyhow!("Missing string vector for key '{}'", left_key))?;
+            Ok((left_key.to_string(), right_key[0].clone()))
+        })
+        .collect::<Result<_>>()?;
+
+    if keys.len() != 1 {
+        return Err(anyhow!(
+            "SimHash joins expect exactly one column pair in `by`"
+        ));
+    }
+
+    let features = SimHashFeatures::new(&features, q.map(|x| x as usize))?;
+    let (idxs1, idxs2, dists) =
+        simhash_join(&df1, &df2, keys[0].clone(), features, max_distance, &pool)
+            .map_err(|e| anyhow!("Error in SimHash join: {e}"))?;
+
+    let dists = DistanceData::Single(&dists);
+    let joined = dispatch_join(
+        how.as_str(),
+        &df1,
+        &df2,
+        idxs1,
+        idxs2,
+        distance_col,
+        dists,
+        by,
+        &output,
+        &pool,
+    )?;
+    Ok(joined)
+}
+
+/// @title Internal: String Aggregating Join via Rust
+/// @description Internal function. Summarizes the string matches of each row of the left table using Rust backend.
+/// @keywords internal
+/// @export
+#[extendr]
+pub fn fozzie_string_aggregate_rs(
+    df1: List,
+    df2: List,
+    by: List,
+    method: Vec<String>,
+    max_distance: Vec<f64>,
+    q: Vec<Rint>,
+    max_prefix: Vec<Rint>,
+    prefix_weight: Vec<Rfloat>,
+    nthread: Option<usize>,
+) -> Result<List> {
+    let pool = get_pool(nthread)?;
+    let keys = extract_keys(&by)?;
+    if keys.len() != 1 {
+        return Err(anyhow!("Aggregating joins match on exactly one column"));
+    }
+    let methods = key_join_methods(
+        &keys,
+        &method,
+        &max_distance,
+        &q,
+        &max_prefix,
+        &prefix_weight,
+    )?;
+    let (left_key, right_key) = &keys[0];
+
+    let summaries = string_summaries(&df1, left_key, &df2, right_key, &methods[0], &pool)
+        .map_err(|e| anyhow!("Error in string aggregate: {e}!"))?;
+    summary_columns(&df1, &summaries, true)
+}
+
+/// @title Internal: Difference Aggregating Join via Rust
+/// @description Internal function. Summarizes the numeric matches of each row of the left table using Rust backend.
+/// @keywords internal
+/// @export
+#[extendr]
+pub fn fozzie_difference_aggregate_rs(
+    df1: List,
+    df2: List,
+    by: List,
+    max_distance: f64,
+    nthread: Option<usize>,
+) -> Result<List> {
+    let pool = get_pool(nthread)?;
+    let keys = extract_keys(&by)?;
+    if keys.len() != 1 {
+        return Err(anyhow!("Aggregating joins match on exactly one column"));
+    }
+    let (left_key, right_key) = &keys[0];
+
+    let summaries = difference_summaries(&df1, left_key, &df2, right_key, max_distance, &pool)
+        .map_err(|e| anyhow!("Error in difference aggregate: {e}!"))?;
+    summary_columns(&df1, &summaries, true)
+}
+
+/// @title Internal: Interval Aggregating Join via Rust
+/// @description Internal function. Counts the intervals of the right table matching each row of the left table using Rust backend.
+/// @keywords internal
+/// @export
+#[extendr]
+pub fn fozzie_interval_aggregate_rs(
+    df1: List,
+    df2: List,
+    by: List,
+    overlap_type: String,
+    maxgap: f64,
+    minoverlap: f64,
+    interval_mode: &str,
+    nthread: Option<usize>,
+) -> Result<List> {
+    let pool = get_pool(nthread)?;
+
+    let counts = match interval_mode {
+        "real" => interval_counts_real(&df1, &df2, &by, &overlap_type, maxgap, minoverlap, &pool),
+        "int" | "integer" => interval_counts_int(
+            &df1,
+            &df2,
+            &by,
+            &overlap_type,
+            maxgap as i32,
+            minoverlap as i32,
+            &pool,
+        ),
+        _ => Err(anyhow!("Unknown interval mode: {}", interval_mode)),
+    }
+    .map_err(|e| anyhow!("Error when counting overlaps: {e}"))?;
+
+    let summaries: Vec<MatchSummary> = counts
+        .into_iter()
+        .map(|count| MatchSummary {
+            count,
+            ..Default::default()
+        })
+        .collect();
+    summary_columns(&df1, &summaries, false)
+}
+
+/// @title Internal: String Match Pairs via Rust
+/// @description Internal function. Returns the row pairs found by the string candidate search using Rust backend.
+/// @keywords internal
+/// @export
+#[extendr]
+pub fn fozzie_string_pairs_rs(
+    df1: List,
+    df2: List,
+    by: List,
+    method: Vec<String>,
+    max_distance: Vec<f64>,
+    q: Vec<Rint>,
+    max_prefix: Vec<Rint>,
+    prefix_weight: Vec<Rfloat>,
+    nthread: Option<usize>,
+) -> Result<List> {
+    let pool = get_pool(nthread)?;
+    let keys = extract_keys(&by)?;
+    let methods = key_join_methods(
+        &keys,
+        &method,
+        &max_distance,
+        &q,
+        &max_prefix,
+        &prefix_weight,
+    )?;
+
+    // As in string joins, the most selective key generates candidates
+    let order = if keys.len() > 1 {
+        key_order(&key_selectivity(&df1, &df2, &keys, &methods, &pool)?)
+    } else {
+        vec![0]
+    };
+    let (left_key, right_key) = &keys[order[0]];
+
+    let pairs = methods[order[0]]
+        .fuzzy_indices(&df1, left_key, &df2, right_key, None, &pool)
+        .map_err(|e| anyhow!("Error in string pairs: {e}!"))?;
+    let (mut idxs1, (mut idxs2, dists)): (Vec<usize>, (Vec<usize>, Vec<f64>)) =
+        pairs.into_iter().map(|(i, j, d)| (i, (j, d))).unzip();
+
+    let mut dists = vec![dists];
+    for &k in &order[1..] {
+        (idxs1, idxs2, dists) = string::difference_pairs(
+            &df1,
+            &idxs1,
+            &df2,
+            &idxs2,
+            &keys[k],
+            &dists,
+            &methods[k],
+            &pool,
+        )
+        .map_err(|e| anyhow!("Error in string pairs: {e}!"))?;
+    }
+    let dists = restore_order(dists, &order);
+    pair_columns(idxs1, idxs2, key_distances(&by, dists), &pool)
+}
+
+/// @title Internal: Difference Match Pairs via Rust
+/// @description Internal function. Returns the row pairs found by the difference candidate search using Rust backend.
+/// @keywords internal
+/// @export
+#[extendr]
+pub fn fozzie_difference_pairs_rs(
+    df1: List,
+    df2: List,
+    by: List,
+    max_distance: f64,
+    nthread: Option<usize>,
+) -> Result<List> {
+    let pool = get_pool(nthread)?;
+    let keys = extract_keys(&by)?;
+
+    // As in difference joins, the most selective key generates candidates
+    let order = if keys.len() > 1 {
+        key_order(&difference_selectivity(
+            &df1,
+            &df2,
+            &keys,
+            max_distance,
+            &pool,
+        )?)
+    } else {
+        vec![0]
+    };
+
+    let (mut idxs1, mut idxs2, dists) =
+        difference_join(&df1, &df2, keys[order[0]].clone(), max_distance, &pool)
+            .map_err(|e| anyhow!("Error in difference pairs: {e}"))?;
+
+    let mut dists = vec![dists];
+    for &k in &order[1..] {
+        (idxs1, idxs2, dists) = difference_pairs(
+            &df1,
+            &idxs1,
+            &df2,
+            &idxs2,
+            &keys[k],
+            &dists,
+            max_distance,
+            &pool,
+        )
+        .map_err(|e| anyhow!("Error in difference pairs: {e}"))?;
+    }
+    let dists = restore_order(dists, &order);
+    pair_columns(idxs1, idxs2, key_distances(&by, dists), &pool)
+}
+
+/// @title Internal: Distance Match Pairs via Rust
+/// @description Internal function. Returns the row pairs found by the distance candidate search using Rust backend.
+/// @keywords internal
+/// @export
+#[extendr]
+pub fn fozzie_distance_pairs_rs(
+    df1: List,
+    df2: List,
+    by: List,
+    method: String,
+    max_distance: f64,
+    nthread: Option<usize>,
+) -> Result<List> {
+    let pool = get_pool(nthread)?;
+
+    let (idxs1, idxs2, dists) = fuzzy_indices_dist(&df1, &df2, &by, &method, max_distance, &pool)
+        .map_err(|e| anyhow!("Error when finding fuzzy matches: {e}"))?;
+    pair_columns(idxs1, idxs2, vec![("distance".to_string(), dists)], &pool)
+}
+
+/// @title Internal: Interval Match Pairs via Rust
+/// @description Internal function. Returns the row pairs found by the interval candidate search using Rust backend.
+/// @keywords internal
+/// @export
+#[extendr]
+pub fn fozzie_interval_pairs_rs(
+    df1: List,
+    df2: List,
+    by: List,
+    overlap_type: String,
+    maxgap: f64,
+    minoverlap: f64,
+    interval_mode: &str,
+    nthread: Option<usize>,
+) -> Result<List> {
+    let pool = get_pool(nthread)?;
+
+    let (idxs1, idxs2) = match interval_mode {
+        "real" => {
+            fuzzy_indices_interval_real(&df1, &df2, &by, &overlap_type, maxgap, minoverlap, &pool)
+        }
+        "int" | "integer" => fuzzy_indices_interval_int(
+            &df1,
+            &df2,
+            &by,
+            &overlap_type,
+            maxgap as i32,
+            minoverlap as i32,
+            &pool,
+        ),
+        _ => Err(anyhow!("Unknown interval mode: {}", interval_mode)),
+    }
+    .map_err(|e| anyhow!("Error when finding fuzzy matches: {e}"))?;
+    pair_columns(idxs1, idxs2, Vec::new(), &pool)
+}
+
+/// @title Internal: Regex Match Pairs via Rust
+/// @description Internal function. Returns the row pairs found by the regex candidate search using Rust backend.
+/// @keywords internal
+/// @export
+#[extendr]
+pub fn fozzie_regex_pairs_rs(
+    df1: List,
+    df2: List,
+    by: List,
+    ignore_case: bool,
+    nthread: Option<usize>,
+) -> Result<List> {
+    let pool = get_pool(nthread)?;
+    let keys = extract_keys(&by)?;
+
+    // As in regex joins, the most selective key generates candidates
+    let order = if keys.len() > 1 {
+        key_order(&regex_selectivity(&df1, &df2, &keys, ignore_case, &pool)?)
+    } else {
+        vec![0]
+    };
+
+    let (mut idxs1, mut idxs2) = regex_join(&df1, &df2, keys[order[0]].clone(), ignore_case, &pool)
+        .map_err(|e| anyhow!("Error in regex pairs: {e}"))?;
+    for &k in &order[1..] {
+        (idxs1, idxs2) = regex_pairs(&df1, &idxs1, &df2, &idxs2, &keys[k], ignore_case, &pool)
+            .map_err(|e| anyhow!("Error in regex pairs: {e}"))?;
+    }
+    pair_columns(idxs1, idxs2, Vec::new(), &pool)
+}
+
 /// @title Get number of threads in global thread pool
 /// @description Returns default rayon number of threads
 /// @keywords internal
@@ -265,9 +957,28 @@ fn get_nthread_default() -> usize {
 extendr_module! {
     mod fozziejoin;
     fn fozzie_string_join_rs;
+    fn fozzie_string_dedupe_rs;
+    fn fozzie_cascade_join_rs;
+    fn fozzie_condition_join_rs;
+    fn fozzie_linkage_join_rs;
+    fn fozzie_string_index_rs;
+    fn fozzie_index_append_rs;
+    fn fozzie_index_save_rs;
+    fn fozzie_index_load_rs;
+    fn fozzie_index_info_rs;
+    fn fozzie_index_join_rs;
     fn fozzie_difference_join_rs;
     fn fozzie_distance_join_rs;
     fn fozzie_interval_join_rs;
     fn fozzie_regex_join_rs;
+    fn fozzie_simhash_join_rs;
+    fn fozzie_string_aggregate_rs;
+    fn fozzie_difference_aggregate_rs;
+    fn fozzie_interval_aggregate_rs;
+    fn fozzie_string_pairs_rs;
+    fn fozzie_difference_pairs_rs;
+    fn fozzie_distance_pairs_rs;
+    fn fozzie_interval_pairs_rs;
+    fn fozzie_regex_pairs_rs;
     fn get_nthread_default;
 }
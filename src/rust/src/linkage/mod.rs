@@ -0,0 +1,308 @@
+use crate::merge::{dispatch_join, key_distance_names, DistanceData, OutputSpec};
+use crate::progress::SearchPool;
+use crate::string::joinmethod::JoinMethod;
+use crate::string::{extract_keys, key_join_methods, slice_key};
+use crate::utils::get_pool;
+use anyhow::{anyhow, Result};
+use extendr_api::prelude::*;
+use itertools::MultiUnzip;
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
+
+/// Agreement levels of a single compared column.
+pub const EXACT: u8 = 0;
+pub const WITHIN: u8 = 1;
+pub const DISAGREE: u8 = 2;
+const NLEVELS: usize = 3;
+
+// Keep estimated probabilities away from 0 and 1, so that log weights stay
+// finite when a level is never (or always) observed.
+const EPS: f64 = 1e-6;
+
+/// Agreement level of every candidate pair on one column: identical strings
+/// agree exactly, pairs within the method's `max_distance` agree partially,
+/// and all others disagree. Pairs with a missing value have no level.
+pub fn agreement_levels(
+    df1: &List,
+    idxs1: &[usize],
+    df2: &List,
+    idxs2: &[usize],
+    by: &(String, String),
+    join_method: &JoinMethod,
+    max_distance: f64,
+    pool: &SearchPool,
+) -> Result<(Vec<Option<u8>>, Vec<f64>)> {
+    let (lk, rk) = (by.0.as_str(), by.1.as_str());
+
+    let vec1_binding = slice_key(df1, lk, idxs1, "df1")?;
+    let vec1: Vec<&str> = vec1_binding
+        .as_str_vector()
+        .ok_or_else(|| anyhow!("Failed to convert df1 column '{}' to string vector", lk))?;
+
+    let vec2_binding = slice_key(df2, rk, idxs2, "df2")?;
+    let vec2: Vec<&str> = vec2_binding
+        .as_str_vector()
+        .ok_or_else(|| anyhow!("Failed to convert df2 column '{}' to string vector", rk))?;
+
+    let out = pool.install(|| {
+        vec1.par_iter()
+            .zip(&vec2)
+            .map(|(a, b)| {
+                if a.is_na() || b.is_na() {
+                    return (None, f64::NAN);
+                }
+                if a == b {
+                    return (Some(EXACT), 0.);
+                }
+                match join_method.distance(a, b) {
+                    Some(d) if d <= max_distance => (Some(WITHIN), d),
+                    Some(d) => (Some(DISAGREE), d),
+                    None => (Some(DISAGREE), f64::NAN),
+                }
+            })
+            .unzip()
+    });
+    Ok(out)
+}
+
+/// Fellegi-Sunter model parameters: the share of candidate pairs that are
+/// matches, and for each column the probability of each agreement level
+/// among matches (`m`) and non-matches (`u`).
+#[derive(Debug, Clone)]
+pub struct FellegiSunter {
+    pub lambda: f64,
+    pub m: Vec<[f64; NLEVELS]>,
+    pub u: Vec<[f64; NLEVELS]>,
+}
+
+impl FellegiSunter {
+    fn new(ncols: usize) -> Self {
+        // Start from the usual assumption that matches mostly agree and
+        // non-matches mostly disagree, which also fixes which of the two
+        // latent classes ends up labeled as matches.
+        FellegiSunter {
+            lambda: 0.1,
+            m: vec![[0.7, 0.2, 0.1]; ncols],
+            u: vec![[0.05, 0.15, 0.8]; ncols],
+        }
+    }
+
+    /// Probability of a comparison pattern among matches and non-matches,
+    /// each weighted by its class share.
+    fn likelihoods(&self, pattern: &[Option<u8>]) -> (f64, f64) {
+        let mut pm = self.lambda;
+        let mut pu = 1. - self.lambda;
+        for (k, level) in pattern.iter().enumerate() {
+            if let Some(l) = level {
+                pm *= self.m[k][*l as usize];
+                pu *= self.u[k][*l as usize];
+            }
+        }
+        (pm, pu)
+    }
+
+    /// Log2 likelihood ratio of a comparison pattern. Missing values add 0.
+    pub fn match_weight(&self, pattern: &[Option<u8>]) -> f64 {
+        pattern
+            .iter()
+            .enumerate()
+            .filter_map(|(k, level)| {
+                level.map(|l| (self.m[k][l as usize] / self.u[k][l as usize]).log2())
+            })
+            .sum()
+    }
+
+    /// Posterior probability that a pair with this pattern is a match.
+    pub fn match_probability(&self, pattern: &[Option<u8>]) -> f64 {
+        let (pm, pu) = self.likelihoods(pattern);
+        pm / (pm + pu)
+    }
+
+    /// Estimate the parameters by expectation-maximization. Pairs are
+    /// grouped by comparison pattern first, so each iteration only visits
+    /// the distinct patterns.
+    pub fn fit(levels: &[Vec<Option<u8>>], max_iter: usize, tol: f64) -> Self {
+        let ncols = levels.len();
+        let npairs = levels.first().map_or(0, |l| l.len());
+
+        let mut counts: FxHashMap<Vec<Option<u8>>, f64> = FxHashMap::default();
+        for p in 0..npairs {
+            let pattern: Vec<Option<u8>> = levels.iter().map(|col| col[p]).collect();
+            *counts.entry(pattern).or_insert(0.) += 1.;
+        }
+        let patterns: Vec<(Vec<Option<u8>>, f64)> = counts.into_iter().collect();
+
+        let mut model = FellegiSunter::new(ncols);
+        if npairs == 0 {
+            return model;
+        }
+
+        for _ in 0..max_iter {
+            // E-step: expected number of matches behind each pattern
+            let posteriors: Vec<f64> = patterns
+                .iter()
+                .map(|(pattern, _)| model.match_probability(pattern))
+                .collect();
+
+            // M-step
+            let mut m = vec![[0.; NLEVELS]; ncols];
+            let mut u = vec![[0.; NLEVELS]; ncols];
+            let mut matches = 0.;
+            for ((pattern, n), w) in patterns.iter().zip(&posteriors) {
+                matches += n * w;
+                for (k, level) in pattern.iter().enumerate() {
+                    if let Some(l) = level {
+                        m[k][*l as usize] += n * w;
+                        u[k][*l as usize] += n * (1. - w);
+                    }
+                }
+            }
+
+            let normalize = |probs: &mut [f64; NLEVELS]| {
+                let total: f64 = probs.iter().sum();
+                for p in probs.iter_mut() {
+                    *p = if total > 0. {
+                        (*p / total).clamp(EPS, 1. - EPS)
+                    } else {
+                        1. / NLEVELS as f64
+                    };
+                }
+            };
+            m.iter_mut().for_each(normalize);
+            u.iter_mut().for_each(normalize);
+
+            let next = FellegiSunter {
+                lambda: (matches / npairs as f64).clamp(EPS, 1. - EPS),
+                m,
+                u,
+            };
+
+            let change = model.distance(&next);
+            model = next;
+            if change < tol {
+                break;
+            }
+        }
+
+        model
+    }
+
+    // Largest absolute change in any parameter between two fits
+    fn distance(&self, other: &FellegiSunter) -> f64 {
+        let probs = |fs: &FellegiSunter| -> Vec<f64> {
+            fs.m.iter()
+                .chain(&fs.u)
+                .flat_map(|p| p.iter().copied())
+                .chain(std::iter::once(fs.lambda))
+                .collect()
+        };
+        probs(self)
+            .iter()
+            .zip(probs(other))
+            .map(|(a, b)| (a - b).abs())
+            .fold(0., f64::max)
+    }
+}
+
+pub fn linkage_join(
+    df1: List,
+    df2: List,
+    by: List,
+    method: Vec<String>,
+    how: String,
+    max_distance: Vec<f64>,
+    q: Vec<Rint>,
+    max_prefix: Vec<Rint>,
+    prefix_weight: Vec<Rfloat>,
+    min_probability: f64,
+    probability_col: String,
+    weight_col: String,
+    distance_col: Option<String>,
+    max_iter: usize,
+    output: OutputSpec,
+    nthread: Option<usize>,
+) -> Result<List> {
+    let keys = extract_keys(&by)?;
+    let pool = get_pool(nthread)?;
+    let join_methods = key_join_methods(
+        &keys,
+        &method,
+        &max_distance,
+        &q,
+        &max_prefix,
+        &prefix_weight,
+    )?;
+
+    if keys.len() < 2 {
+        return Err(anyhow!(
+            "Linkage joins need at least two columns in `by`: the first finds candidate pairs, the others are scored"
+        ));
+    }
+
+    // The first column is a hard block: candidate pairs are the pairs within
+    // its `max_distance`, so it cannot disagree and is not scored
+    let (left_key, right_key) = &keys[0];
+    let mut matchdat =
+        join_methods[0].fuzzy_indices(&df1, left_key, &df2, right_key, None, &pool)?;
+    matchdat.sort_unstable_by_key(|a| (a.0, a.1));
+    let (idxs1, idxs2, block_dists): (Vec<usize>, Vec<usize>, Vec<f64>) =
+        matchdat.into_iter().multiunzip();
+
+    let mut levels = Vec::with_capacity(keys.len() - 1);
+    let mut dists = vec![block_dists];
+    for ((bypair, join_method), &threshold) in
+        keys.iter().zip(&join_methods).zip(&max_distance).skip(1)
+    {
+        let (lv, d) = agreement_levels(
+            &df1,
+            &idxs1,
+            &df2,
+            &idxs2,
+            bypair,
+            join_method,
+            threshold,
+            &pool,
+        )?;
+        levels.push(lv);
+        dists.push(d);
+    }
+
+    let model = FellegiSunter::fit(&levels, max_iter, 1e-8);
+
+    let mut keep = Vec::new();
+    let mut probabilities = Vec::new();
+    let mut weights = Vec::new();
+    for p in 0..idxs1.len() {
+        let pattern: Vec<Option<u8>> = levels.iter().map(|col| col[p]).collect();
+        let prob = model.match_probability(&pattern);
+        if prob >= min_probability {
+            keep.push(p);
+            probabilities.push(prob);
+            weights.push(model.match_weight(&pattern));
+        }
+    }
+
+    let mut columns = vec![(probability_col, probabilities), (weight_col, weights)];
+    if let Some(distance_col) = distance_col {
+        for (name, col) in key_distance_names(&by, &distance_col)
+            .into_iter()
+            .zip(&dists)
+        {
+            columns.push((name, keep.iter().map(|&p| col[p]).collect()));
+        }
+    }
+
+    let out = dispatch_join(
+        how.as_str(),
+        &df1,
+        &df2,
+        keep.iter().map(|&p| idxs1[p]).collect(),
+        keep.iter().map(|&p| idxs2[p]).collect(),
+        None,
+        DistanceData::Named(&columns),
+        by,
+        &output,
+        &pool,
+    )?;
+    Ok(out)
+}
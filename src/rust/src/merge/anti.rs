@@ -1,11 +1,17 @@
-use crate::merge::{subset_and_label, Merge};
+use crate::merge::{subset_and_label, unmatched_rows, Merge};
+use crate::planner::nrow;
 use extendr_api::prelude::*;
 
 impl Merge {
-    pub fn anti(df1: &List, idx1: Vec<usize>) -> List {
-        let lhs_len = df1.index(1).unwrap().len();
-        let lhs_complement: Vec<usize> = (1..=lhs_len).filter(|i| !idx1.contains(i)).collect();
-        let (names, combined) = subset_and_label(df1, &lhs_complement);
-        List::from_names_and_values(names, combined).unwrap()
+    pub fn anti(df1: &List, idx1: Vec<usize>) -> anyhow::Result<List> {
+        let lhs_complement = unmatched_rows(nrow(df1), &idx1);
+        let (names, combined) = subset_and_label(df1, &lhs_complement)?;
+        List::from_names_and_values(names, combined).map_err(|e| anyhow::anyhow!("{e}"))
+    }
+
+    pub fn right_anti(df2: &List, idx2: Vec<usize>) -> anyhow::Result<List> {
+        let rhs_complement = unmatched_rows(nrow(df2), &idx2);
+        let (names, combined) = subset_and_label(df2, &rhs_complement)?;
+        List::from_names_and_values(names, combined).map_err(|e| anyhow::anyhow!("{e}"))
     }
 }
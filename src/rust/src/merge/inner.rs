@@ -1,48 +1,81 @@
 use crate::merge::{
-    build_distance_columns, build_single_distance_column, subset_and_label, DistanceData, Merge,
+    coalesce_column, distance_columns, join_keys, matched_rows, take_column, take_columns,
+    DistanceData, Merge, OutputSpec,
 };
 use extendr_api::prelude::*;
 use rustc_hash::FxHashSet;
 
+/// Columns of both tables taken from their output rows, named and picked as
+/// set by `output`: the names both tables share are suffixed, and without
+/// `keep` the left-hand join columns are filled in from the right-hand ones,
+/// which are dropped.
 pub fn merge_and_label_with_suffix(
     df1: &List,
-    idx1: &[usize],
+    rows1: &[Option<usize>],
     df2: &List,
-    idx2: &[usize],
-) -> (Vec<String>, Vec<Robj>) {
-    let (n1_raw, c1) = subset_and_label(df1, idx1);
-    let (n2_raw, c2) = subset_and_label(df2, idx2);
+    rows2: &[Option<usize>],
+    by: &List,
+    output: &OutputSpec,
+) -> anyhow::Result<(Vec<String>, Vec<Robj>)> {
+    let keys = join_keys(by);
 
-    let n1_set: FxHashSet<String> = n1_raw.iter().cloned().collect();
-    let n2_set: FxHashSet<String> = n2_raw.iter().cloned().collect();
-    let overlap: FxHashSet<String> = n1_set.intersection(&n2_set).cloned().collect();
+    let (n1_raw, c1) = if output.keep {
+        take_columns(df1, rows1)?
+    } else {
+        df1.iter()
+            .map(|(name, col)| {
+                let col = match keys.iter().find(|(left, _)| *left == name) {
+                    Some((_, right)) => {
+                        let col2 = df2.dollar(right.as_str()).unwrap_or_else(|_| ().into());
+                        coalesce_column(&col, rows1, &col2, rows2)
+                    }
+                    None => take_column(&col, rows1),
+                }
+                .map_err(|e| anyhow::anyhow!("Failed to merge column `{}`: {}", name, e))?;
+                Ok((name.to_string(), col))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?
+            .into_iter()
+            .unzip()
+    };
 
-    let n1 = n1_raw
-        .into_iter()
-        .map(|name| {
-            if overlap.contains(&name) {
-                format!("{}{}", name, ".x")
-            } else {
-                name
-            }
+    // Only the right-hand columns asked for are taken at all
+    let (n2_raw, c2): (Vec<String>, Vec<Robj>) = df2
+        .iter()
+        .filter(|(name, _)| output.takes_right(name, &keys))
+        .map(|(name, col)| {
+            let col = take_column(&col, rows2)
+                .map_err(|e| anyhow::anyhow!("Failed to merge column `{}`: {}", name, e))?;
+            Ok((name.to_string(), col))
         })
-        .collect::<Vec<_>>();
-
-    let n2 = n2_raw
+        .collect::<anyhow::Result<Vec<_>>>()?
         .into_iter()
-        .map(|name| {
-            if overlap.contains(&name) {
-                format!("{}{}", name, ".y")
-            } else {
-                name
-            }
-        })
-        .collect::<Vec<_>>();
+        .unzip();
+
+    let n1_set: FxHashSet<&String> = n1_raw.iter().collect();
+    let overlap: FxHashSet<String> = n2_raw
+        .iter()
+        .filter(|name| n1_set.contains(name))
+        .cloned()
+        .collect();
 
-    (
-        n1.into_iter().chain(n2).collect(),
+    let [suffix1, suffix2] = &output.suffix;
+    let label = |name: String, suffix: &str| {
+        if overlap.contains(&name) {
+            format!("{}{}", name, suffix)
+        } else {
+            name
+        }
+    };
+
+    Ok((
+        n1_raw
+            .into_iter()
+            .map(|name| label(name, suffix1))
+            .chain(n2_raw.into_iter().map(|name| label(name, suffix2)))
+            .collect(),
         c1.into_iter().chain(c2).collect(),
-    )
+    ))
 }
 
 impl Merge {
@@ -54,24 +87,21 @@ impl Merge {
         distance_col: Option<String>,
         dist: DistanceData,
         by: List,
-    ) -> List {
-        let (mut names, mut values) = merge_and_label_with_suffix(&df1, &idx1, &df2, &idx2);
+        output: &OutputSpec,
+    ) -> anyhow::Result<List> {
+        let (mut names, mut values) = merge_and_label_with_suffix(
+            df1,
+            &matched_rows(&idx1),
+            df2,
+            &matched_rows(&idx2),
+            &by,
+            output,
+        )?;
 
-        if let Some(colname) = distance_col {
-            match dist {
-                DistanceData::Single(vec) => {
-                    let (name, col) = build_single_distance_column(vec, &colname);
-                    names.push(name);
-                    values.push(col);
-                }
-                DistanceData::Matrix(mat) => {
-                    let (dist_names, dist_cols) = build_distance_columns(mat, &by, &colname);
-                    names.extend(dist_names);
-                    values.extend(dist_cols);
-                }
-            }
-        }
+        let (dist_names, dist_cols) = distance_columns(dist, &by, distance_col.as_deref());
+        names.extend(dist_names);
+        values.extend(dist_cols);
 
-        List::from_names_and_values(names, values).unwrap()
+        List::from_names_and_values(names, values).map_err(|e| anyhow::anyhow!("{e}"))
     }
 }
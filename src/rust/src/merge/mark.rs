@@ -0,0 +1,52 @@
+use crate::merge::{DistanceData, Merge};
+use crate::planner::nrow;
+use extendr_api::prelude::*;
+
+// Name of the column counting the matches of each left row
+const COUNT_COL: &str = "n_matches";
+
+impl Merge {
+    /// `df1` unchanged, plus the number of matches of each row, or, with
+    /// `distance_col`, the distance of its closest match (`NA` if none).
+    pub fn mark(
+        df1: &List,
+        idx1: Vec<usize>,
+        distance_col: Option<String>,
+        dist: DistanceData,
+    ) -> anyhow::Result<List> {
+        let n1 = nrow(df1);
+        let (mut names, mut values): (Vec<String>, Vec<Robj>) = df1
+            .iter()
+            .map(|(name, col)| (name.to_string(), col))
+            .unzip();
+
+        let scored = distance_col.and_then(|name| Some((name, dist.pair_scores()?)));
+        match scored {
+            Some((distance_col, scores)) => {
+                let mut closest = vec![f64::INFINITY; n1];
+                for (&i, score) in idx1.iter().zip(scores) {
+                    closest[i - 1] = closest[i - 1].min(score);
+                }
+                let closest = Doubles::from_values(closest.into_iter().map(|d| {
+                    if d.is_finite() {
+                        Rfloat::from(d)
+                    } else {
+                        Rfloat::na()
+                    }
+                }));
+                names.push(distance_col);
+                values.push(closest.into_robj());
+            }
+            None => {
+                let mut counts = vec![0i32; n1];
+                for &i in &idx1 {
+                    counts[i - 1] += 1;
+                }
+                names.push(COUNT_COL.to_string());
+                values.push(counts.into_robj());
+            }
+        }
+
+        List::from_names_and_values(names, values).map_err(|e| anyhow::anyhow!("{e}"))
+    }
+}
@@ -1,3 +1,5 @@
+use crate::progress::SearchPool;
+use crate::utils::summed_distances;
 use extendr_api::prelude::*;
 
 pub struct Merge;
@@ -5,6 +7,8 @@ pub mod anti;
 pub mod full;
 pub mod inner;
 pub mod left;
+pub mod mark;
+pub mod nest;
 pub mod right;
 pub mod semi;
 
@@ -12,83 +16,394 @@ pub fn dispatch_join(
     how: &str,
     df1: &List,
     df2: &List,
-    idxs1: Vec<usize>,
-    idxs2: Vec<usize>,
+    mut idxs1: Vec<usize>,
+    mut idxs2: Vec<usize>,
     distance_col: Option<String>,
     dist: DistanceData,
     by: List,
-) -> List {
-    match how {
-        "inner" => Merge::inner(df1, df2, idxs1, idxs2, distance_col, dist, by),
-        "left" => Merge::left(df1, df2, idxs1, idxs2, distance_col, dist, by),
-        "right" => Merge::right(df1, df2, idxs1, idxs2, distance_col, dist, by),
-        "full" => Merge::full(df1, df2, idxs1, idxs2, distance_col, dist, by),
-        "anti" => Merge::anti(df1, idxs1),
-        "semi" => Merge::semi(df1, idxs1),
-        _ => panic!("Unknown join type: {}", how),
-    }
-}
-
-/// Combine two Robj vectors of the same type into one.
-/// Preserves all attributes from `a`, including class, levels, label, etc.
-pub fn combine_robj(a: &Robj, b: &Robj) -> Result<Robj> {
-    // Ensure both inputs are of the same R type
-    if a.rtype() != b.rtype() {
-        return Err(Error::Other("Cannot combine: mismatched types".to_string()));
-    }
-
-    // Special case for list columns (e.g. POSIXlt or nested tibbles)
-    if a.rtype() == Rtype::List {
-        let list_a = a
-            .as_list()
-            .ok_or_else(|| Error::Other("Failed to parse list a".to_string()))?;
-        let list_b = b
-            .as_list()
-            .ok_or_else(|| Error::Other("Failed to parse list b".to_string()))?;
-        let merged = list_a
-            .iter()
-            .chain(list_b.iter())
-            .map(|(_, v)| v.clone())
-            .collect::<Vec<_>>();
-        let mut combined = List::from_values(merged).as_robj().clone();
-
-        // Copy all attributes from `a`
-        if let Some(attr_list) = a.get_attrib("attributes") {
-            if let Some(attr_pairs) = attr_list.as_list() {
-                for (key, val) in attr_pairs.iter() {
-                    combined.set_attrib(key, val)?;
+    output: &OutputSpec,
+    pool: &SearchPool,
+) -> anyhow::Result<List> {
+    let kept = kept_matches(idxs1.len(), matches!(how, "inner" | "right_semi"), pool)?;
+
+    // Keep the first pairs, with their distances
+    let owned;
+    let dist = match kept {
+        Some(m) => {
+            idxs1.truncate(m);
+            idxs2.truncate(m);
+            owned = dist.truncated(m);
+            owned.data()
+        }
+        None => dist,
+    };
+
+    let mut out = match how {
+        "inner" => Merge::inner(df1, df2, idxs1, idxs2, distance_col, dist, by, output)?,
+        "left" => Merge::left(df1, df2, idxs1, idxs2, distance_col, dist, by, output)?,
+        "right" => Merge::right(df1, df2, idxs1, idxs2, distance_col, dist, by, output)?,
+        "full" => Merge::full(df1, df2, idxs1, idxs2, distance_col, dist, by, output)?,
+        "nest" => Merge::nest(df1, df2, idxs1, idxs2, distance_col, dist, by, output)?,
+        "anti" => Merge::anti(df1, idxs1)?,
+        "semi" => Merge::semi(df1, idxs1)?,
+        "right_anti" => Merge::right_anti(df2, idxs2)?,
+        "right_semi" => Merge::right_semi(df2, idxs2)?,
+        "mark" => Merge::mark(df1, idxs1, distance_col, dist)?,
+        _ => return Err(anyhow::anyhow!("Unknown join type: {}", how)),
+    };
+
+    // Flag the result so the R side can warn that matches were dropped
+    if let Some(m) = kept {
+        out.set_attrib("fozzie_truncated", m as f64)
+            .map_err(|e| anyhow::anyhow!("{e}"))?;
+    }
+    Ok(out)
+}
+
+/// Number of the `n` matched pairs to keep under `fozzie.max_matches`, or
+/// `None` if none were dropped, by the searches on `pool` or here. Only joins
+/// that use each pair on its own, such as inner joins, can be cut short: the
+/// others derive unmatched rows and counts from every match, so they fail
+/// instead.
+pub fn kept_matches(n: usize, truncates: bool, pool: &SearchPool) -> anyhow::Result<Option<usize>> {
+    let limit = match MatchLimit::from_options()? {
+        Some(limit) if pool.stopped() || n > limit.max_matches => limit,
+        _ => return Ok(None),
+    };
+    if !limit.truncate {
+        return Err(anyhow::anyhow!(
+            "Join found {} matches, more than `fozzie.max_matches` ({}). \
+             Tighten `max_distance` or raise the limit",
+            n,
+            limit.max_matches
+        ));
+    }
+    if !truncates {
+        return Err(anyhow::anyhow!(
+            "Join found more than `fozzie.max_matches` ({}) matches, and only inner \
+             and right semi joins can keep some of them. Tighten `max_distance` or \
+             raise the limit",
+            limit.max_matches
+        ));
+    }
+    Ok(Some(n.min(limit.max_matches)))
+}
+
+/// Limit on the number of matched pairs a join may return, set with
+/// `options(fozzie.max_matches = n)`. Joins over the limit fail, or keep the
+/// first `n` pairs with `options(fozzie.max_matches_action = "truncate")`.
+pub struct MatchLimit {
+    pub max_matches: usize,
+    pub truncate: bool,
+}
+
+impl MatchLimit {
+    pub fn from_options() -> anyhow::Result<Option<Self>> {
+        let limit =
+            eval_string("getOption('fozzie.max_matches')").map_err(|e| anyhow::anyhow!("{e}"))?;
+        if limit.is_null() {
+            return Ok(None);
+        }
+        let max_matches = limit
+            .as_real()
+            .or_else(|| limit.as_integer().map(f64::from))
+            .filter(|x| *x >= 0.)
+            .ok_or_else(|| anyhow::anyhow!("`fozzie.max_matches` must be a non-negative number"))?;
+
+        let action = eval_string("getOption('fozzie.max_matches_action', 'error')")
+            .map_err(|e| anyhow::anyhow!("{e}"))?;
+        let truncate = match action.as_str() {
+            Some("error") => false,
+            Some("truncate") => true,
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "`fozzie.max_matches_action` must be \"error\" or \"truncate\""
+                ))
+            }
+        };
+
+        Ok(Some(MatchLimit {
+            max_matches: max_matches as usize,
+            truncate,
+        }))
+    }
+}
+
+/// How a join names and picks its output columns. Names both tables share
+/// get `suffix`, the right-hand join columns are dropped unless `keep` is
+/// set, and only the other right-hand columns in `right_columns`, if given,
+/// are carried over.
+pub struct OutputSpec {
+    pub suffix: [String; 2],
+    pub keep: bool,
+    pub right_columns: Option<Vec<String>>,
+}
+
+impl Default for OutputSpec {
+    fn default() -> Self {
+        OutputSpec {
+            suffix: [".x".to_string(), ".y".to_string()],
+            keep: true,
+            right_columns: None,
+        }
+    }
+}
+
+impl OutputSpec {
+    /// Read the `suffix`, `keep` and `right_columns` settings passed from R.
+    pub fn from_list(list: &List) -> anyhow::Result<Self> {
+        let mut spec = OutputSpec::default();
+        for (name, value) in list.iter() {
+            match name {
+                "suffix" => {
+                    let suffix = value
+                        .as_string_vector()
+                        .filter(|s| s.len() == 2)
+                        .ok_or_else(|| anyhow::anyhow!("`suffix` must be two strings"))?;
+                    spec.suffix = [suffix[0].clone(), suffix[1].clone()];
+                }
+                "keep" => {
+                    spec.keep = value
+                        .as_bool()
+                        .ok_or_else(|| anyhow::anyhow!("`keep` must be TRUE or FALSE"))?;
                 }
+                "right_columns" => spec.right_columns = value.as_string_vector(),
+                _ => return Err(anyhow::anyhow!("Unknown output setting: {}", name)),
             }
         }
+        Ok(spec)
+    }
+
+    /// Whether a right-hand column is carried over, given the join columns
+    /// as pairs of left-hand and right-hand names.
+    pub fn takes_right(&self, name: &str, keys: &[(String, String)]) -> bool {
+        if keys.iter().any(|(_, right)| right == name) {
+            return self.keep;
+        }
+        self.right_columns
+            .as_ref()
+            .map_or(true, |columns| columns.iter().any(|c| c == name))
+    }
+}
+
+/// Join columns of `by`, as pairs of left-hand and right-hand names.
+pub fn join_keys(by: &List) -> Vec<(String, String)> {
+    by.iter()
+        .filter_map(|(left, right)| Some((left.to_string(), right.as_str_vector()?[0].to_string())))
+        .collect()
+}
+
+/// Rows of a table with `n` rows that are not in `idx`, in order. Matched
+/// rows are marked in a bitset, so this is linear in `n` and `idx`. Rows are
+/// 1-based.
+pub fn unmatched_rows(n: usize, idx: &[usize]) -> Vec<usize> {
+    let mut matched = vec![0u64; n.div_ceil(64)];
+    for &i in idx {
+        matched[(i - 1) / 64] |= 1 << ((i - 1) % 64);
+    }
+    (1..=n)
+        .filter(|&i| matched[(i - 1) / 64] & (1 << ((i - 1) % 64)) == 0)
+        .collect()
+}
+
+/// Build an output column from the rows of an input column, in a single
+/// allocation of the input's type. Each output row takes a 1-based row of
+/// the input, or `NA` for `None`. Attributes other than names and
+/// dimensions, such as the levels of factors, the time zone of date-times
+/// and the units of time differences, are carried over. Lists are padded
+/// with `NULL`, and data frame and record columns are taken field by field.
+pub fn take_column(col: &Robj, rows: &[Option<usize>]) -> Result<Robj> {
+    if col.inherits("data.frame") {
+        return take_data_frame(col, rows);
+    }
+    if is_record(col) {
+        return take_record(col, rows);
+    }
+    let mut out = take_values(col, rows)?;
+    for (name, value) in column_attributes(col)? {
+        out.set_attrib(name.as_str(), value)?;
+    }
+    Ok(out)
+}
+
+/// The values of `take_column`, without the attributes of the input column.
+/// Not for data frame or record columns.
+pub fn take_values(col: &Robj, rows: &[Option<usize>]) -> Result<Robj> {
+    let type_error = || Error::Other(format!("Cannot merge a column of type {:?}", col.rtype()));
+
+    let out: Robj = match col.rtype() {
+        Rtype::Integers => {
+            let src = col.as_integer_slice().ok_or_else(type_error)?;
+            Integers::from_values(
+                rows.iter()
+                    .map(|r| r.map_or(Rint::na(), |r| src[r - 1].into())),
+            )
+            .into()
+        }
+        Rtype::Doubles => {
+            let src = col.as_real_slice().ok_or_else(type_error)?;
+            // bit64 stores its values as the raw bits of a double, with NA as
+            // the smallest 64-bit integer
+            let na = if col.inherits("integer64") {
+                f64::from_bits(i64::MIN as u64).into()
+            } else {
+                Rfloat::na()
+            };
+            Doubles::from_values(rows.iter().map(|r| r.map_or(na, |r| src[r - 1].into()))).into()
+        }
+        Rtype::Logicals => {
+            let src = col.as_logical_slice().ok_or_else(type_error)?;
+            Logicals::from_values(rows.iter().map(|r| r.map_or(Rbool::na(), |r| src[r - 1]))).into()
+        }
+        Rtype::Strings => {
+            let src = col.as_str_vector().ok_or_else(type_error)?;
+            Strings::from_values(rows.iter().map(|r| r.map_or(<&str>::na(), |r| src[r - 1]))).into()
+        }
+        Rtype::Complexes => {
+            let src = Complexes::try_from(col.clone()).map_err(|_| type_error())?;
+            Complexes::from_values(rows.iter().map(|r| r.map_or(Rcplx::na(), |r| src[r - 1])))
+                .into()
+        }
+        Rtype::Raw => {
+            // Raw vectors have no NA, so R pads them with zero bytes
+            let src = col.as_raw_slice().ok_or_else(type_error)?;
+            let bytes: Vec<u8> = rows.iter().map(|r| r.map_or(0, |r| src[r - 1])).collect();
+            Raw::from_bytes(&bytes).into()
+        }
+        Rtype::List => {
+            let list = col.as_list().ok_or_else(type_error)?;
+            let src = list.as_slice();
+            List::from_values(
+                rows.iter()
+                    .map(|r| r.map_or_else(|| ().into(), |r| src[r - 1].clone())),
+            )
+            .into()
+        }
+        _ => return Err(type_error()),
+    };
+    Ok(out)
+}
 
-        return Ok(combined);
+/// Attributes of a column carried over to the columns taken from it: its
+/// class, levels, time zone, units and the like.
+pub fn column_attributes(col: &Robj) -> Result<Vec<(String, Robj)>> {
+    let Some(attributes) = call!("attributes", col)?.as_list() else {
+        return Ok(Vec::new());
+    };
+    Ok(attributes
+        .iter()
+        .filter(|(name, _)| !matches!(*name, "names" | "dim" | "dimnames"))
+        .map(|(name, value)| (name.to_string(), value))
+        .collect())
+}
+
+/// A left-hand join column taken from its rows, with the rows that only
+/// exist on the right filled in from the right-hand join column, as for
+/// `keep = FALSE`.
+pub fn coalesce_column(
+    col1: &Robj,
+    rows1: &[Option<usize>],
+    col2: &Robj,
+    rows2: &[Option<usize>],
+) -> Result<Robj> {
+    let fill: Vec<usize> = (0..rows1.len())
+        .filter(|&i| rows1[i].is_none() && rows2[i].is_some())
+        .collect();
+    let out = take_column(col1, rows1)?;
+    if fill.is_empty() {
+        return Ok(out);
     }
 
-    // For atomic vectors, use R's native `c()` function
-    let mut combined = call!("c", a, b)?;
+    let fill_rows: Vec<Option<usize>> = fill.iter().map(|&i| rows2[i]).collect();
+    let values = take_column(col2, &fill_rows)?;
+    let at: Vec<i32> = fill.iter().map(|&i| i as i32 + 1).collect();
+    call!("[<-", out, at, values)
+}
 
-    // Copy all attributes from `a`
-    if let Some(attr_list) = a.get_attrib("attributes") {
-        if let Some(attr_pairs) = attr_list.as_list() {
-            for (key, val) in attr_pairs.iter() {
-                combined.set_attrib(key, val)?;
+// A data frame column of a data frame, such as a packed tibble column, with
+// each of its own columns taken from the rows
+fn take_data_frame(col: &Robj, rows: &[Option<usize>]) -> Result<Robj> {
+    let df = col
+        .as_list()
+        .ok_or_else(|| Error::Other("Data frame column is not a list".to_string()))?;
+    let mut names = Vec::with_capacity(df.len());
+    let mut columns = Vec::with_capacity(df.len());
+    for (name, inner) in df.iter() {
+        names.push(name.to_string());
+        columns.push(take_column(&inner, rows)?);
+    }
+    let mut out = List::from_names_and_values(names, columns)?.into_robj();
+
+    if let Some(attributes) = call!("attributes", col)?.as_list() {
+        for (name, value) in attributes.iter() {
+            if !matches!(name, "names" | "row.names") {
+                out.set_attrib(name, value)?;
             }
         }
     }
+    // Compact row names, as R stores them for `1:n`
+    out.set_attrib("row.names", [Rint::na(), Rint::from(-(rows.len() as i32))])?;
+    Ok(out)
+}
 
-    Ok(combined)
+/// Whether a column is a list holding one vector per field, each with a value
+/// per row, rather than a value per row: `POSIXlt` date-times and `vctrs`
+/// records such as those of `vctrs::new_rcrd()`.
+pub fn is_record(col: &Robj) -> bool {
+    col.is_list() && (col.inherits("POSIXlt") || col.inherits("vctrs_rcrd"))
 }
 
-/// Helper to subset and label columns from a data frame
-pub fn subset_and_label(df: &List, indices: &[usize]) -> (Vec<String>, Vec<Robj>) {
-    let mut names = Vec::with_capacity(df.ncols());
-    let mut columns = Vec::with_capacity(df.ncols());
+// A record column with each of its fields taken from the rows. Records whose
+// fields are not all of the same length, such as unbalanced `POSIXlt`
+// values, are left to R's `[`.
+fn take_record(col: &Robj, rows: &[Option<usize>]) -> Result<Robj> {
+    let fields = col
+        .as_list()
+        .ok_or_else(|| Error::Other("Record column is not a list".to_string()))?;
+    let n = fields.values().next().map_or(0, |field| field.len());
+    if fields.values().any(|field| field.len() != n) {
+        let at: Vec<Rint> = rows
+            .iter()
+            .map(|r| r.map_or(Rint::na(), |r| Rint::from(r as i32)))
+            .collect();
+        return call!("[", col, Integers::from_values(at));
+    }
+
+    let mut names = Vec::with_capacity(fields.len());
+    let mut taken = Vec::with_capacity(fields.len());
+    for (name, field) in fields.iter() {
+        names.push(name.to_string());
+        taken.push(take_column(&field, rows)?);
+    }
+    let mut out = List::from_names_and_values(names, taken)?.into_robj();
+    for (name, value) in column_attributes(col)? {
+        out.set_attrib(name.as_str(), value)?;
+    }
+    Ok(out)
+}
+
+/// Output columns taken from the rows of a table, see `take_column`.
+pub fn take_columns(df: &List, rows: &[Option<usize>]) -> anyhow::Result<(Vec<String>, Vec<Robj>)> {
+    let mut names = Vec::with_capacity(df.len());
+    let mut columns = Vec::with_capacity(df.len());
     for (name, col) in df.iter() {
-        let vals = col.slice(indices).unwrap();
+        let col = take_column(&col, rows)
+            .map_err(|e| anyhow::anyhow!("Failed to merge column `{}`: {}", name, e))?;
         names.push(name.to_string());
-        columns.push(vals);
+        columns.push(col);
     }
-    (names, columns)
+    Ok((names, columns))
+}
+
+/// Output rows taking each of `idx` in turn, to be followed by other rows
+/// or padding.
+pub fn matched_rows(idx: &[usize]) -> Vec<Option<usize>> {
+    idx.iter().map(|&i| Some(i)).collect()
+}
+
+/// Helper to subset and label columns from a data frame
+pub fn subset_and_label(df: &List, indices: &[usize]) -> anyhow::Result<(Vec<String>, Vec<Robj>)> {
+    take_columns(df, &matched_rows(indices))
 }
 
 /// Helper to construct distance columns
@@ -101,16 +416,11 @@ pub fn build_distance_columns(
     let mut columns = Vec::with_capacity(dist.len());
 
     let ndist = dist.len();
-    for (x, (y, z)) in dist.iter().zip(by.iter()) {
+    for (x, cname) in dist.iter().zip(key_distance_names(by, distance_col)) {
         let cname = if ndist == 1 {
             distance_col.to_string()
         } else {
-            format!(
-                "{}_{}_{}",
-                distance_col,
-                y,
-                z.as_str_vector().expect("hi")[0]
-            )
+            cname
         };
         names.push(cname);
         columns.push(x.into_robj());
@@ -119,23 +429,143 @@ pub fn build_distance_columns(
     (names, columns)
 }
 
+/// Helper to name one distance column per key, e.g. `dist_col1_col2`
+pub fn key_distance_names(by: &List, distance_col: &str) -> Vec<String> {
+    by.iter()
+        .map(|(y, z)| {
+            format!(
+                "{}_{}_{}",
+                distance_col,
+                y,
+                z.as_str_vector().expect("hi")[0]
+            )
+        })
+        .collect()
+}
+
 /// Helper to construct single distance column
 pub fn build_single_distance_column(dist: &[f64], distance_col: &str) -> (String, Robj) {
     (distance_col.to_string(), dist.to_vec().into_robj())
 }
 
-/// Pad a column with R-style NA values based on its type
-pub fn pad_column(col: &Robj, pad_len: usize) -> Robj {
-    match col.rtype() {
-        Rtype::Integers => Robj::from(vec![Rint::na(); pad_len]),
-        Rtype::Doubles => Robj::from(vec![Rfloat::na(); pad_len]),
-        Rtype::Logicals => Robj::from(vec![Rbool::na(); pad_len]),
-        Rtype::Strings => Robj::from(vec![Rstr::na(); pad_len]),
-        _ => Robj::from(vec![Robj::from(()); pad_len]),
+/// Helper to construct the distance column(s) of any `DistanceData`. Only
+/// `Named` columns are added when no `distance_col` is requested.
+pub fn distance_columns(
+    dist: DistanceData,
+    by: &List,
+    distance_col: Option<&str>,
+) -> (Vec<String>, Vec<Robj>) {
+    if let DistanceData::Named(cols) = dist {
+        return cols
+            .iter()
+            .map(|(name, col)| (name.clone(), col.clone().into_robj()))
+            .unzip();
+    }
+    let Some(distance_col) = distance_col else {
+        return (Vec::new(), Vec::new());
+    };
+
+    match dist {
+        DistanceData::Single(vec) => {
+            let (name, col) = build_single_distance_column(vec, distance_col);
+            (vec![name], vec![col])
+        }
+        DistanceData::Matrix(mat) => build_distance_columns(mat, by, distance_col),
+        DistanceData::Labeled(mat, labels) => {
+            let (names, cols) = build_distance_columns(mat, by, distance_col);
+            let names = names
+                .into_iter()
+                .zip(labels)
+                .map(|(name, label)| format!("{}_{}", name, label))
+                .collect();
+            (names, cols)
+        }
+        DistanceData::Composite(score, per_key) => {
+            let (mut names, mut cols) = match *per_key {
+                // Always name per-key columns by key, as `distance_col` holds the score
+                DistanceData::Matrix(mat) if mat.len() == 1 => (
+                    key_distance_names(by, distance_col),
+                    vec![mat[0].clone().into_robj()],
+                ),
+                other => distance_columns(other, by, Some(distance_col)),
+            };
+            names.insert(0, distance_col.to_string());
+            cols.insert(0, score.clone().into_robj());
+            (names, cols)
+        }
+        DistanceData::Named(_) => unreachable!(),
     }
 }
 
 pub enum DistanceData<'a> {
     Single(&'a Vec<f64>),
     Matrix(&'a Vec<Vec<f64>>),
+    /// One column per key, with a label appended to each column name
+    Labeled(&'a Vec<Vec<f64>>, &'a Vec<String>),
+    /// A combined score, followed by the per-key distances it was built from
+    Composite(&'a Vec<f64>, Box<DistanceData<'a>>),
+    /// Columns with fixed names, added whether or not `distance_col` is set
+    Named(&'a Vec<(String, Vec<f64>)>),
+}
+
+impl DistanceData<'_> {
+    /// Copy of the distances of the first `n` pairs.
+    pub fn truncated(&self, n: usize) -> OwnedDistances {
+        let cut = |col: &Vec<f64>| col[..n.min(col.len())].to_vec();
+        match self {
+            DistanceData::Single(vec) => OwnedDistances::Single(cut(vec)),
+            DistanceData::Matrix(mat) => OwnedDistances::Matrix(mat.iter().map(cut).collect()),
+            DistanceData::Labeled(mat, labels) => {
+                OwnedDistances::Labeled(mat.iter().map(cut).collect(), labels.to_vec())
+            }
+            DistanceData::Composite(score, per_key) => {
+                OwnedDistances::Composite(cut(score), Box::new(per_key.truncated(n)))
+            }
+            DistanceData::Named(cols) => OwnedDistances::Named(
+                cols.iter()
+                    .map(|(name, col)| (name.clone(), cut(col)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// Owned counterpart of `DistanceData`, for distances built by the merge
+/// itself.
+pub enum OwnedDistances {
+    Single(Vec<f64>),
+    Matrix(Vec<Vec<f64>>),
+    Labeled(Vec<Vec<f64>>, Vec<String>),
+    Composite(Vec<f64>, Box<OwnedDistances>),
+    Named(Vec<(String, Vec<f64>)>),
+}
+
+impl DistanceData<'_> {
+    /// Distance of each pair for ranking matches: summed across keys, or the
+    /// combined score. `None` for `Named` columns, which need not be
+    /// distances.
+    pub fn pair_scores(&self) -> Option<Vec<f64>> {
+        match self {
+            DistanceData::Single(vec) => Some(vec.to_vec()),
+            DistanceData::Matrix(mat) | DistanceData::Labeled(mat, _) => {
+                Some(summed_distances(mat))
+            }
+            DistanceData::Composite(score, _) => Some(score.to_vec()),
+            DistanceData::Named(_) => None,
+        }
+    }
+}
+
+impl OwnedDistances {
+    pub fn data(&self) -> DistanceData<'_> {
+        match self {
+            OwnedDistances::Single(vec) => DistanceData::Single(vec),
+            OwnedDistances::Matrix(mat) => DistanceData::Matrix(mat),
+            OwnedDistances::Labeled(mat, labels) => DistanceData::Labeled(mat, labels),
+            OwnedDistances::Composite(score, per_key) => {
+                DistanceData::Composite(score, Box::new(per_key.data()))
+            }
+            OwnedDistances::Named(cols) => DistanceData::Named(cols),
+        }
+    }
 }
@@ -0,0 +1,124 @@
+use crate::merge::{
+    column_attributes, distance_columns, is_record, join_keys, take_column, take_values,
+    DistanceData, Merge, OutputSpec,
+};
+use crate::planner::nrow;
+use extendr_api::prelude::*;
+
+// Name of the list-column holding the matches of each left row
+const NEST_COL: &str = "matches";
+
+impl Merge {
+    /// Each row of `df1` once, in order, with a list-column of data frames
+    /// holding its matching rows of `df2` and their distances. Rows without
+    /// matches get a data frame with no rows.
+    pub fn nest(
+        df1: &List,
+        df2: &List,
+        idx1: Vec<usize>,
+        idx2: Vec<usize>,
+        distance_col: Option<String>,
+        dist: DistanceData,
+        by: List,
+        output: &OutputSpec,
+    ) -> anyhow::Result<List> {
+        if df1.iter().any(|(name, _)| name == NEST_COL) {
+            return Err(anyhow::anyhow!(
+                "`df1` already has a `{NEST_COL}` column; rename it to nest the matches"
+            ));
+        }
+        let n1 = nrow(df1);
+
+        // Positions of the pairs of each left row, in match order: those of
+        // the 1-based row `r` are `positions[starts[r - 1]..starts[r]]`
+        let mut starts = vec![0usize; n1 + 1];
+        for &i in &idx1 {
+            starts[i] += 1;
+        }
+        for r in 1..=n1 {
+            starts[r] += starts[r - 1];
+        }
+        let mut next = starts.clone();
+        let mut positions = vec![0usize; idx1.len()];
+        for (p, &i) in idx1.iter().enumerate() {
+            positions[next[i - 1]] = p;
+            next[i - 1] += 1;
+        }
+
+        // Attributes are looked up once per column, not once per left row
+        let keys = join_keys(&by);
+        let columns: Vec<_> = df2
+            .iter()
+            .filter(|(name, _)| output.takes_right(name, &keys))
+            .map(|(name, col)| {
+                let attributes = column_attributes(&col)
+                    .map_err(|e| anyhow::anyhow!("Failed to merge column `{}`: {}", name, e))?;
+                Ok((name.to_string(), col, attributes))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let (dist_names, dist_cols) = distance_columns(dist, &by, distance_col.as_deref());
+        let dist_cols: Vec<&[f64]> = dist_cols
+            .iter()
+            .zip(&dist_names)
+            .map(|(col, name)| {
+                col.as_real_slice()
+                    .ok_or_else(|| anyhow::anyhow!("Distance column `{}` is not numeric", name))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        let mut names: Vec<String> = columns.iter().map(|(name, _, _)| name.clone()).collect();
+        names.extend(dist_names);
+        let class = df2.class().map(|class| class.collect::<Vec<_>>());
+
+        let mut nested = Vec::with_capacity(n1);
+        for r in 0..n1 {
+            let pairs = &positions[starts[r]..starts[r + 1]];
+            let rows: Vec<Option<usize>> = pairs.iter().map(|&p| Some(idx2[p])).collect();
+
+            let mut values = Vec::with_capacity(names.len());
+            for (name, col, attributes) in &columns {
+                let taken = if col.inherits("data.frame") || is_record(col) {
+                    take_column(col, &rows)
+                } else {
+                    take_values(col, &rows).and_then(|mut taken| {
+                        for (attr, value) in attributes {
+                            taken.set_attrib(attr.as_str(), value)?;
+                        }
+                        Ok(taken)
+                    })
+                };
+                values
+                    .push(taken.map_err(|e| {
+                        anyhow::anyhow!("Failed to merge column `{}`: {}", name, e)
+                    })?);
+            }
+            for col in &dist_cols {
+                let taken: Vec<f64> = pairs.iter().map(|&p| col[p]).collect();
+                values.push(taken.into_robj());
+            }
+
+            let mut frame = List::from_names_and_values(&names, values)
+                .map_err(|e| anyhow::anyhow!("{e}"))?
+                .into_robj();
+            match &class {
+                Some(class) => frame.set_class(class),
+                None => frame.set_class(["data.frame"]),
+            }
+            .map_err(|e| anyhow::anyhow!("{e}"))?;
+            frame
+                .set_attrib("row.names", [Rint::na(), Rint::from(-(rows.len() as i32))])
+                .map_err(|e| anyhow::anyhow!("{e}"))?;
+            nested.push(frame);
+        }
+        let nested = List::from_values(nested);
+
+        let (mut out_names, mut out_values): (Vec<String>, Vec<Robj>) = df1
+            .iter()
+            .map(|(name, col)| (name.to_string(), col))
+            .unzip();
+        out_names.push(NEST_COL.to_string());
+        out_values.push(nested.into_robj());
+
+        List::from_names_and_values(out_names, out_values).map_err(|e| anyhow::anyhow!("{e}"))
+    }
+}
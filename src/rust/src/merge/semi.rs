@@ -2,11 +2,15 @@ use crate::merge::{subset_and_label, Merge};
 use extendr_api::prelude::*;
 
 impl Merge {
-    pub fn semi(df1: &List, mut idx1: Vec<usize>) -> List {
+    pub fn semi(df1: &List, mut idx1: Vec<usize>) -> anyhow::Result<List> {
         idx1.sort_unstable();
         idx1.dedup();
 
-        let (names, combined) = subset_and_label(df1, &idx1);
-        List::from_names_and_values(names, combined).unwrap()
+        let (names, combined) = subset_and_label(df1, &idx1)?;
+        List::from_names_and_values(names, combined).map_err(|e| anyhow::anyhow!("{e}"))
+    }
+
+    pub fn right_semi(df2: &List, idx2: Vec<usize>) -> anyhow::Result<List> {
+        Merge::semi(df2, idx2)
     }
 }
@@ -0,0 +1,64 @@
+use crate::merge::{kept_matches, key_distance_names};
+use crate::progress::SearchPool;
+use extendr_api::prelude::*;
+
+/// Distance columns of pairs found on the keys of `by`, one vector per key:
+/// `distance` for a single key, and `distance_{left}_{right}` for each of
+/// several, as named by the joins' `distance_col`.
+pub fn key_distances(by: &List, dists: Vec<Vec<f64>>) -> Vec<(String, Vec<f64>)> {
+    if dists.len() == 1 {
+        return vec![(
+            "distance".to_string(),
+            dists.into_iter().next().unwrap_or_default(),
+        )];
+    }
+    key_distance_names(by, "distance")
+        .into_iter()
+        .zip(dists)
+        .collect()
+}
+
+/// Matched pairs as columns `i` and `j`, the 1-based rows of `df1` and
+/// `df2`, sorted by `i` then `j`, plus the distance columns of families that
+/// measure one. Pairs are limited by `fozzie.max_matches` like those of an
+/// inner join.
+pub fn pair_columns(
+    idxs1: Vec<usize>,
+    idxs2: Vec<usize>,
+    dists: Vec<(String, Vec<f64>)>,
+    pool: &SearchPool,
+) -> anyhow::Result<List> {
+    let kept = kept_matches(idxs1.len(), true, pool)?;
+    let mut order: Vec<usize> = (0..idxs1.len()).collect();
+    order.sort_unstable_by_key(|&p| (idxs1[p], idxs2[p]));
+    if let Some(m) = kept {
+        order.truncate(m);
+    }
+
+    let rows = |idxs: &[usize]| {
+        order
+            .iter()
+            .map(|&p| idxs[p] as i32)
+            .collect::<Vec<_>>()
+            .into_robj()
+    };
+    let mut names = vec!["i".to_string(), "j".to_string()];
+    let mut values = vec![rows(&idxs1), rows(&idxs2)];
+    for (name, dists) in dists {
+        names.push(name);
+        values.push(
+            order
+                .iter()
+                .map(|&p| dists[p])
+                .collect::<Vec<_>>()
+                .into_robj(),
+        );
+    }
+
+    let mut out = List::from_names_and_values(names, values).map_err(|e| anyhow::anyhow!("{e}"))?;
+    if let Some(m) = kept {
+        out.set_attrib("fozzie_truncated", m as f64)
+            .map_err(|e| anyhow::anyhow!("{e}"))?;
+    }
+    Ok(out)
+}
@@ -0,0 +1,74 @@
+use anyhow::{anyhow, Result};
+use extendr_api::prelude::*;
+use rustc_hash::FxHashSet;
+
+/// Rows sampled from each table to estimate selectivity. Keys are checked on
+/// every pair of sampled rows.
+pub const SAMPLE_ROWS: usize = 32;
+
+/// Number of rows of a data frame.
+pub fn nrow(df: &List) -> usize {
+    df.index(1).map_or(0, |col| col.len())
+}
+
+/// Evenly spaced 1-based rows of a table with `n` rows, at most `size` of
+/// them.
+pub fn sample_rows(n: usize, size: usize) -> Vec<usize> {
+    if n <= size {
+        return (1..=n).collect();
+    }
+    (0..size).map(|k| k * n / size + 1).collect()
+}
+
+/// Every pair of the rows sampled from each table, as 1-based indices.
+pub fn sample_pairs(df1: &List, df2: &List) -> (Vec<usize>, Vec<usize>) {
+    let rows1 = sample_rows(nrow(df1), SAMPLE_ROWS);
+    let rows2 = sample_rows(nrow(df2), SAMPLE_ROWS);
+    rows1
+        .iter()
+        .flat_map(|&i| rows2.iter().map(move |&j| (i, j)))
+        .unzip()
+}
+
+/// Number of distinct values in a string or numeric column.
+pub fn distinct_count(df: &List, key: &str) -> Result<usize> {
+    let col = df
+        .dollar(key)
+        .map_err(|_| anyhow!("Missing column '{}'", key))?;
+    if let Some(v) = col.as_str_vector() {
+        Ok(v.into_iter().collect::<FxHashSet<&str>>().len())
+    } else if let Some(v) = col.as_real_slice() {
+        Ok(v.iter()
+            .map(|x| x.to_bits())
+            .collect::<FxHashSet<u64>>()
+            .len())
+    } else if let Some(v) = col.as_integer_slice() {
+        Ok(v.iter().collect::<FxHashSet<&i32>>().len())
+    } else {
+        Err(anyhow!("Column '{}' is neither string nor numeric", key))
+    }
+}
+
+/// Estimated fraction of all pairs matching a key, from the number of
+/// sampled pairs that matched. The larger number of distinct values on
+/// either side breaks ties between keys no sampled pair matched.
+pub fn selectivity(hits: usize, sampled: usize, distinct: usize) -> f64 {
+    (hits as f64 + 1.0 / distinct.max(1) as f64) / (sampled as f64 + 1.0)
+}
+
+/// Key positions ordered from the most to the least selective, keeping the
+/// given order on ties.
+pub fn key_order(estimates: &[f64]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..estimates.len()).collect();
+    order.sort_by(|&a, &b| estimates[a].total_cmp(&estimates[b]));
+    order
+}
+
+/// Put per-key values found in `order` back in the order of the keys.
+pub fn restore_order<T>(values: Vec<T>, order: &[usize]) -> Vec<T> {
+    let mut slots: Vec<Option<T>> = (0..order.len()).map(|_| None).collect();
+    for (value, &k) in values.into_iter().zip(order) {
+        slots[k] = Some(value);
+    }
+    slots.into_iter().flatten().collect()
+}
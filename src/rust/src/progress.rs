@@ -0,0 +1,220 @@
+use crate::merge::MatchLimit;
+use anyhow::{anyhow, Result};
+use extendr_api::prelude::*;
+use rayon::ThreadPool;
+use std::ops::Deref;
+use std::os::raw::{c_int, c_void};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::time::Duration;
+
+// How often the R thread checks on a running search
+const POLL: Duration = Duration::from_millis(100);
+
+// Width of the progress bar, in characters
+const BAR_WIDTH: usize = 30;
+
+extern "C" {
+    fn R_ToplevelExec(fun: extern "C" fn(*mut c_void), data: *mut c_void) -> c_int;
+    fn R_CheckUserInterrupt();
+}
+
+extern "C" fn check_interrupt(_data: *mut c_void) {
+    unsafe { R_CheckUserInterrupt() }
+}
+
+// Whether the user pressed Ctrl-C (or Esc) in R. `R_CheckUserInterrupt`
+// jumps to the top level on an interrupt, so it runs in a top-level context
+// of its own, which then reports failure instead.
+fn user_interrupted() -> bool {
+    unsafe { R_ToplevelExec(check_interrupt, std::ptr::null_mut()) == 0 }
+}
+
+/// Thread pool of one join call, with the state of the searches it runs:
+/// the work done and matches found, whether the user interrupted them, and
+/// whether one stopped at `fozzie.max_matches`. Every join builds its own
+/// with `get_pool()`, so joins running at the same time, such as one started
+/// by another package while a search is watched, never share this state. It
+/// dereferences to the `rayon::ThreadPool` for work that is not counted.
+pub struct SearchPool {
+    pool: ThreadPool,
+    // Whether a search is running with the R thread watching it
+    watching: AtomicBool,
+    // Set once the user interrupts the running search
+    cancelled: AtomicBool,
+    // Units of work done by the running search
+    done: AtomicUsize,
+    // Matches found by the running search, and how many it may find
+    found: AtomicUsize,
+    budget: AtomicUsize,
+    // Set once a search stopped at `fozzie.max_matches` with its matches kept
+    stopped: AtomicBool,
+}
+
+impl SearchPool {
+    pub fn new(pool: ThreadPool) -> Self {
+        SearchPool {
+            pool,
+            watching: AtomicBool::new(false),
+            cancelled: AtomicBool::new(false),
+            done: AtomicUsize::new(0),
+            found: AtomicUsize::new(0),
+            budget: AtomicUsize::new(usize::MAX),
+            stopped: AtomicBool::new(false),
+        }
+    }
+
+    /// Count one unit of work of the running search, such as one value
+    /// searched. Returns false once the join was interrupted, or found more
+    /// matches than `fozzie.max_matches`, so that the rest of the work is
+    /// skipped.
+    pub fn tick(&self) -> bool {
+        if self.cancelled.load(Ordering::Relaxed)
+            || self.found.load(Ordering::Relaxed) > self.budget.load(Ordering::Relaxed)
+        {
+            return false;
+        }
+        self.done.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    /// Count `n` matches found by the running search, towards
+    /// `fozzie.max_matches`.
+    pub fn found(&self, n: usize) {
+        self.found.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Whether a search of this join stopped at `fozzie.max_matches`, so that
+    /// some matches are missing.
+    pub fn stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+}
+
+impl Deref for SearchPool {
+    type Target = ThreadPool;
+
+    fn deref(&self) -> &ThreadPool {
+        &self.pool
+    }
+}
+
+/// Run a search on `pool` like `ThreadPool::install`, for `total` units of
+/// work counted with `SearchPool::tick()`. Meanwhile the R thread checks for
+/// user interrupts and, with `options(fozzie.progress = TRUE)`, reports
+/// progress. An interrupted search stops early and returns an error.
+///
+/// Searches also stop once they find more matches than
+/// `options(fozzie.max_matches = n)`. They then fail, or with
+/// `options(fozzie.max_matches_action = "truncate")` return what they found,
+/// which `SearchPool::stopped()` reports.
+///
+/// Searches started from within another one run unwatched, and their work
+/// and matches count towards the outer search.
+pub fn install<T, F>(pool: &SearchPool, total: usize, op: F) -> Result<T>
+where
+    T: Send,
+    F: FnOnce() -> T + Send,
+{
+    if rayon::current_thread_index().is_some() || pool.watching.swap(true, Ordering::SeqCst) {
+        return Ok(pool.install(op));
+    }
+    let _watch = Watch(pool);
+    pool.cancelled.store(false, Ordering::SeqCst);
+    pool.done.store(0, Ordering::SeqCst);
+    pool.found.store(0, Ordering::SeqCst);
+    let limit = MatchLimit::from_options()?;
+    if let Some(limit) = &limit {
+        pool.budget.store(limit.max_matches, Ordering::SeqCst);
+    }
+    let mut bar = ProgressBar::from_options(total)?;
+
+    let out = std::thread::scope(|scope| {
+        let (done_tx, done_rx) = mpsc::channel();
+        let search = scope.spawn(move || {
+            let out = pool.install(op);
+            let _ = done_tx.send(());
+            out
+        });
+        while let Err(mpsc::RecvTimeoutError::Timeout) = done_rx.recv_timeout(POLL) {
+            if !pool.cancelled.load(Ordering::Relaxed) && user_interrupted() {
+                pool.cancelled.store(true, Ordering::Relaxed);
+            }
+            if let Some(bar) = &mut bar {
+                bar.draw(pool.done.load(Ordering::Relaxed));
+            }
+        }
+        search.join()
+    });
+    let out = out.unwrap_or_else(|e| std::panic::resume_unwind(e));
+
+    if let Some(bar) = bar {
+        bar.finish(pool.done.load(Ordering::Relaxed));
+    }
+    if pool.cancelled.load(Ordering::SeqCst) {
+        return Err(anyhow!("Join interrupted by the user"));
+    }
+    if let Some(limit) = limit.filter(|l| pool.found.load(Ordering::SeqCst) > l.max_matches) {
+        if !limit.truncate {
+            return Err(anyhow!(
+                "Join found more than {} matches (`fozzie.max_matches`). \
+                 Tighten `max_distance` or raise the limit",
+                limit.max_matches
+            ));
+        }
+        pool.stopped.store(true, Ordering::SeqCst);
+    }
+    Ok(out)
+}
+
+// Lets the next search of the pool be watched and run without a match limit,
+// even if this one panics
+struct Watch<'a>(&'a SearchPool);
+
+impl Drop for Watch<'_> {
+    fn drop(&mut self) {
+        self.0.budget.store(usize::MAX, Ordering::SeqCst);
+        self.0.watching.store(false, Ordering::SeqCst);
+    }
+}
+
+// Progress of a search, drawn on one line of R's console once it runs past
+// the first check, so quick searches print nothing
+struct ProgressBar {
+    total: usize,
+    shown: Option<usize>,
+}
+
+impl ProgressBar {
+    fn from_options(total: usize) -> Result<Option<Self>> {
+        let enabled = eval_string("isTRUE(getOption('fozzie.progress'))")
+            .map_err(|e| anyhow!("{e}"))?
+            .as_bool()
+            .unwrap_or(false);
+        Ok((enabled && total > 0).then_some(ProgressBar { total, shown: None }))
+    }
+
+    fn draw(&mut self, done: usize) {
+        let done = done.min(self.total);
+        if self.shown == Some(done) {
+            return;
+        }
+        let filled = done * BAR_WIDTH / self.total;
+        reprint!(
+            "\r[{}{}] {}/{} ({}%)",
+            "=".repeat(filled),
+            " ".repeat(BAR_WIDTH - filled),
+            done,
+            self.total,
+            done * 100 / self.total
+        );
+        self.shown = Some(done);
+    }
+
+    fn finish(mut self, done: usize) {
+        if self.shown.is_some() {
+            self.draw(done);
+            reprintln!();
+        }
+    }
+}
@@ -1,14 +1,15 @@
+use crate::planner::{distinct_count, sample_pairs, selectivity};
+use crate::progress::{install, SearchPool};
 use anyhow::{anyhow, Result};
 use extendr_api::prelude::*;
 use rayon::prelude::*;
-use rayon::ThreadPool;
 use regex::{RegexBuilder, RegexSetBuilder};
 
 pub fn fuzzy_indices_regex(
     values: &[&str],
     patterns: &[&str],
     ignore_case: bool,
-    pool: &ThreadPool,
+    pool: &SearchPool,
 ) -> Result<(Vec<usize>, Vec<usize>)> {
     // Compile RegexSet from all patterns
     let regex_set = RegexSetBuilder::new(patterns)
@@ -17,18 +18,18 @@ pub fn fuzzy_indices_regex(
         .map_err(|e| anyhow!("Failed to build RegexSet: {}", e))?;
 
     // Parallel match over values
-    let (lhs_indices, rhs_indices): (Vec<usize>, Vec<usize>) = pool.install(|| {
+    let (lhs_indices, rhs_indices): (Vec<usize>, Vec<usize>) = install(pool, values.len(), || {
         values
             .par_iter()
             .enumerate()
+            .filter(|_| pool.tick())
             .flat_map_iter(|(i_idx, value)| {
-                regex_set
-                    .matches(value)
-                    .into_iter()
-                    .map(move |j_idx| (i_idx + 1, j_idx + 1))
+                let matches = regex_set.matches(value);
+                pool.found(matches.len());
+                matches.into_iter().map(move |j_idx| (i_idx + 1, j_idx + 1))
             })
             .unzip()
-    });
+    })?;
 
     Ok((lhs_indices, rhs_indices))
 }
@@ -38,7 +39,7 @@ pub fn regex_join(
     df2: &List,
     by: (String, String),
     ignore_case: bool,
-    pool: &ThreadPool,
+    pool: &SearchPool,
 ) -> Result<(Vec<usize>, Vec<usize>)> {
     let lk = by.0.as_str();
     let rk = by.1.as_str();
@@ -62,54 +63,85 @@ pub fn regex_join(
     fuzzy_indices_regex(&values_vec, &patterns_vec, ignore_case, pool)
 }
 
-pub fn regex_pairs(
+/// Positions of the given pairs whose `df1` value matches the `df2` pattern.
+pub fn regex_positions(
     df1: &List,
-    idxs1: &Vec<usize>,
+    idxs1: &[usize],
     df2: &List,
-    idxs2: &Vec<usize>,
+    idxs2: &[usize],
     by: &(String, String),
     ignore_case: bool,
-    pool: &ThreadPool,
-) -> Result<(Vec<usize>, Vec<usize>)> {
+    pool: &SearchPool,
+) -> Result<Vec<usize>> {
     let lk = by.0.as_str();
     let rk = by.1.as_str();
 
     let values_binding = df1
         .dollar(lk)
-        .map_err(|_| anyhow!("Column `{}` not found in df1", lk))?;
+        .map_err(|_| anyhow!("Column `{}` not found in df1", lk))?
+        .slice(idxs1)
+        .map_err(|_| anyhow!("Failed to slice df1 column `{}`", lk))?;
 
-    let vec1 = values_binding
+    let values = values_binding
         .as_str_vector()
         .ok_or_else(|| anyhow!("Column `{}` in df1 is not a string", lk))?;
 
     let patterns_binding = df2
         .dollar(rk)
-        .map_err(|_| anyhow!("Column `{}` not found in df2", rk))?;
+        .map_err(|_| anyhow!("Column `{}` not found in df2", rk))?
+        .slice(idxs2)
+        .map_err(|_| anyhow!("Failed to slice df2 column `{}`", rk))?;
 
-    let vec2 = patterns_binding
+    let patterns = patterns_binding
         .as_str_vector()
         .ok_or_else(|| anyhow!("Column `{}` in df2 is not a string", rk))?;
 
-    let matched: Vec<(usize, usize)> = pool.install(|| {
-        vec1.par_iter()
-            .zip(vec2)
+    let positions = pool.install(|| {
+        values
+            .par_iter()
+            .zip(patterns)
             .enumerate()
-            .filter_map(|(i, (pattern, target))| {
+            .filter_map(|(i, (value, pattern))| {
                 RegexBuilder::new(pattern)
                     .case_insensitive(ignore_case)
                     .build()
                     .ok()
-                    .and_then(|re| {
-                        if re.is_match(&target) {
-                            Some((idxs1[i], idxs2[i]))
-                        } else {
-                            None
-                        }
-                    })
+                    .filter(|re| re.is_match(value))
+                    .map(|_| i)
             })
             .collect()
     });
+    Ok(positions)
+}
 
-    let (idxs1b, idxs2b): (Vec<usize>, Vec<usize>) = matched.into_iter().unzip();
-    Ok((idxs1b, idxs2b))
+/// Estimated selectivity of each key, from a sample of pairs checked
+/// against their patterns.
+pub fn regex_selectivity(
+    df1: &List,
+    df2: &List,
+    keys: &[(String, String)],
+    ignore_case: bool,
+    pool: &SearchPool,
+) -> Result<Vec<f64>> {
+    let (rows1, rows2) = sample_pairs(df1, df2);
+    keys.iter()
+        .map(|bypair| {
+            let hits = regex_positions(df1, &rows1, df2, &rows2, bypair, ignore_case, pool)?;
+            let distinct = distinct_count(df1, &bypair.0)?.max(distinct_count(df2, &bypair.1)?);
+            Ok(selectivity(hits.len(), rows1.len(), distinct))
+        })
+        .collect()
+}
+
+pub fn regex_pairs(
+    df1: &List,
+    idxs1: &Vec<usize>,
+    df2: &List,
+    idxs2: &Vec<usize>,
+    by: &(String, String),
+    ignore_case: bool,
+    pool: &SearchPool,
+) -> Result<(Vec<usize>, Vec<usize>)> {
+    let keep = regex_positions(df1, idxs1, df2, idxs2, by, ignore_case, pool)?;
+    Ok(keep.iter().map(|&p| (idxs1[p], idxs2[p])).unzip())
 }
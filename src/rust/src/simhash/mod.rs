@@ -0,0 +1,291 @@
+use crate::progress::{install, SearchPool};
+use crate::utils::get_qgrams;
+use anyhow::{anyhow, Result};
+use extendr_api::prelude::*;
+use itertools::iproduct;
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+#[derive(Debug, Clone, Copy)]
+pub enum SimHashFeatures {
+    Tokens,
+    QGram(usize),
+    Precomputed,
+}
+
+impl SimHashFeatures {
+    pub fn new(s: &str, q: Option<usize>) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "tokens" => Ok(SimHashFeatures::Tokens),
+            "qgram" => {
+                Ok(SimHashFeatures::QGram(q.ok_or_else(|| {
+                    anyhow!("Must provide `q` for `qgram` features")
+                })?))
+            }
+            "precomputed" => Ok(SimHashFeatures::Precomputed),
+            _ => Err(anyhow!("Unknown SimHash feature type: {}", s)),
+        }
+    }
+}
+
+// FNV-1a followed by the murmur3 finalizer, so that short features still
+// spread evenly over all 64 bits.
+fn hash_feature(s: &str) -> u64 {
+    let mut h: u64 = 0xcbf29ce484222325;
+    for b in s.bytes() {
+        h ^= b as u64;
+        h = h.wrapping_mul(0x100000001b3);
+    }
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51afd7ed558ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ceb9fe1a85ec53);
+    h ^= h >> 33;
+    h
+}
+
+/// Weighted 64-bit SimHash of a bag of features. Returns `None` if the bag is
+/// empty, so that blank strings never match each other.
+pub fn simhash(features: &FxHashMap<&str, usize>) -> Option<u64> {
+    if features.is_empty() {
+        return None;
+    }
+
+    let mut votes = [0i64; 64];
+    for (feature, &weight) in features {
+        let h = hash_feature(feature);
+        for (bit, vote) in votes.iter_mut().enumerate() {
+            if (h >> bit) & 1 == 1 {
+                *vote += weight as i64;
+            } else {
+                *vote -= weight as i64;
+            }
+        }
+    }
+
+    let fp = votes
+        .iter()
+        .enumerate()
+        .filter(|(_, &v)| v > 0)
+        .fold(0u64, |acc, (bit, _)| acc | (1 << bit));
+    Some(fp)
+}
+
+fn get_tokens(s: &str) -> FxHashMap<&str, usize> {
+    let mut tokens = FxHashMap::default();
+    for tok in s.split_whitespace() {
+        *tokens.entry(tok).or_insert(0) += 1;
+    }
+    tokens
+}
+
+fn parse_hex(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let s = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    u64::from_str_radix(s, 16).ok()
+}
+
+/// Extract one fingerprint per row. Rows that are `NA` (or have no features)
+/// are `None` and never match.
+pub fn fingerprints(df: &List, key: &str, features: SimHashFeatures) -> Result<Vec<Option<u64>>> {
+    let col = df
+        .dollar(key)
+        .map_err(|_| anyhow!("Column `{}` not found", key))?;
+
+    match features {
+        SimHashFeatures::Tokens | SimHashFeatures::QGram(_) => {
+            let strs = col
+                .as_str_iter()
+                .ok_or_else(|| anyhow!("Column `{}` is not a string vector", key))?;
+            Ok(strs
+                .map(|s| {
+                    if s.is_na() {
+                        return None;
+                    }
+                    match features {
+                        SimHashFeatures::QGram(q) => simhash(&get_qgrams(s, q)),
+                        _ => simhash(&get_tokens(s)),
+                    }
+                })
+                .collect())
+        }
+        SimHashFeatures::Precomputed => {
+            if let Some(strs) = col.as_str_iter() {
+                strs.map(|s| {
+                    if s.is_na() {
+                        return Ok(None);
+                    }
+                    parse_hex(s)
+                        .map(Some)
+                        .ok_or_else(|| anyhow!("Invalid hex fingerprint `{}` in `{}`", s, key))
+                })
+                .collect()
+            } else if col.inherits("integer64") {
+                // bit64 stores its values as the raw bits of a double
+                let vals = col
+                    .as_real_slice()
+                    .ok_or_else(|| anyhow!("Column `{}` is not a valid integer64", key))?;
+                Ok(vals
+                    .iter()
+                    .map(|x| {
+                        let bits = x.to_bits();
+                        if bits as i64 == i64::MIN {
+                            None
+                        } else {
+                            Some(bits)
+                        }
+                    })
+                    .collect())
+            } else if let Some(vals) = col.as_integer_slice() {
+                Ok(vals
+                    .iter()
+                    .map(|&x| {
+                        if x.is_na() {
+                            None
+                        } else {
+                            Some(x as u32 as u64)
+                        }
+                    })
+                    .collect())
+            } else if let Some(vals) = col.as_real_slice() {
+                Ok(vals
+                    .iter()
+                    .map(|&x| {
+                        if x.is_na() {
+                            None
+                        } else {
+                            Some(x as i64 as u64)
+                        }
+                    })
+                    .collect())
+            } else {
+                Err(anyhow!(
+                    "Column `{}` must be character (hex), integer, double or integer64",
+                    key
+                ))
+            }
+        }
+    }
+}
+
+// Group row numbers (1-based) by identical fingerprint.
+fn group_fingerprints(fps: &[Option<u64>]) -> Vec<(u64, Vec<usize>)> {
+    let mut groups: FxHashMap<u64, Vec<usize>> = FxHashMap::default();
+    for (i, fp) in fps.iter().enumerate() {
+        if let Some(fp) = fp {
+            groups.entry(*fp).or_default().push(i + 1);
+        }
+    }
+    groups.into_iter().collect()
+}
+
+/// Find all pairs of fingerprints within `max_distance` differing bits.
+///
+/// Uses multi-index hashing: the 64 bits are split into `max_distance + 1`
+/// disjoint blocks, and by the pigeonhole principle any pair within
+/// `max_distance` bits agrees exactly on at least one block. Each block gets
+/// its own hash table over the right-hand fingerprints, and only pairs that
+/// collide in some table are verified with a popcount. `max_distance` must
+/// be less than 64, as checked by `simhash_join()`.
+pub fn fuzzy_indices_simhash(
+    left: &[Option<u64>],
+    right: &[Option<u64>],
+    max_distance: u32,
+    pool: &SearchPool,
+) -> Result<Vec<(usize, usize, f64)>> {
+    let left_groups = group_fingerprints(left);
+    let right_groups = group_fingerprints(right);
+
+    let nblocks = max_distance as usize + 1;
+    let blocks: Vec<(u32, u64)> = (0..nblocks)
+        .map(|b| {
+            let start = b * 64 / nblocks;
+            let end = (b + 1) * 64 / nblocks;
+            let width = end - start;
+            let mask = if width == 64 {
+                u64::MAX
+            } else {
+                (1u64 << width) - 1
+            };
+            (start as u32, mask)
+        })
+        .collect();
+
+    let mut tables: Vec<FxHashMap<u64, Vec<usize>>> = vec![FxHashMap::default(); nblocks];
+    for (idx, (fp, _)) in right_groups.iter().enumerate() {
+        for (table, &(shift, mask)) in tables.iter_mut().zip(&blocks) {
+            table.entry((fp >> shift) & mask).or_default().push(idx);
+        }
+    }
+
+    let idxs = install(pool, left_groups.len(), || {
+        left_groups
+            .par_iter()
+            .filter(|_| pool.tick())
+            .flat_map_iter(|(fp1, v1)| {
+                let mut seen: FxHashSet<usize> = FxHashSet::default();
+                let mut out = Vec::new();
+                for (table, &(shift, mask)) in tables.iter().zip(&blocks) {
+                    let Some(candidates) = table.get(&((fp1 >> shift) & mask)) else {
+                        continue;
+                    };
+                    for &c in candidates {
+                        if !seen.insert(c) {
+                            continue;
+                        }
+                        let (fp2, v2) = &right_groups[c];
+                        let dist = (fp1 ^ fp2).count_ones();
+                        if dist <= max_distance {
+                            iproduct!(v1, v2).for_each(|(a, b)| {
+                                out.push((*a, *b, dist as f64));
+                            });
+                        }
+                    }
+                }
+                pool.found(out.len());
+                out
+            })
+            .collect()
+    })?;
+
+    Ok(idxs)
+}
+
+pub fn simhash_join(
+    df1: &List,
+    df2: &List,
+    by: (String, String),
+    features: SimHashFeatures,
+    max_distance: f64,
+    pool: &SearchPool,
+) -> Result<(Vec<usize>, Vec<usize>, Vec<f64>)> {
+    if !(0.0..64.0).contains(&max_distance) {
+        return Err(anyhow!(
+            "`max_distance` must be at least 0 and less than 64 bits, not {}",
+            max_distance
+        ));
+    }
+    let lk = by.0.as_str();
+    let rk = by.1.as_str();
+
+    let fps1 = fingerprints(df1, lk, features)?;
+    let fps2 = fingerprints(df2, rk, features)?;
+
+    let mut matchdat = fuzzy_indices_simhash(&fps1, &fps2, max_distance as u32, pool)?;
+    matchdat.sort_unstable_by_key(|a| (a.0, a.1));
+
+    let mut idxs1 = Vec::with_capacity(matchdat.len());
+    let mut idxs2 = Vec::with_capacity(matchdat.len());
+    let mut dists = Vec::with_capacity(matchdat.len());
+
+    for (i, j, d) in matchdat {
+        idxs1.push(i);
+        idxs2.push(j);
+        dists.push(d);
+    }
+
+    Ok((idxs1, idxs2, dists))
+}
@@ -1,13 +1,15 @@
+use crate::explain::{compared, recording};
+use crate::progress::SearchPool;
 use crate::string::edit::EditDistance;
+use crate::string::topk::MatchCollector;
 use extendr_api::prelude::*;
-use itertools::iproduct;
 use rayon::prelude::*;
 use rustc_hash::FxHashMap;
 
 pub struct LCSStr;
 
 impl LCSStr {
-    fn compute(&self, s1: &str, s2: &str) -> usize {
+    pub fn compute(&self, s1: &str, s2: &str) -> usize {
         let m = s1.len();
         let n = s2.len();
         let mut dp = vec![vec![0; n + 1]; m + 1];
@@ -32,7 +34,7 @@ impl EditDistance for LCSStr {
         left: &Vec<&str>,
         right: &Vec<&str>,
         max_distance: &f64,
-        pool: &rayon::ThreadPool,
+        pool: &SearchPool,
     ) -> (Vec<usize>, Vec<f64>) {
         let (keep, dists): (Vec<usize>, Vec<f64>) = pool.install(|| {
             left.par_iter()
@@ -57,27 +59,35 @@ impl EditDistance for LCSStr {
     fn compare_one_to_many(
         &self,
         k1: &str,
-        v1: &Vec<usize>,
         length_map: &FxHashMap<usize, Vec<&str>>,
         idx_map: &FxHashMap<&str, Vec<usize>>,
         max_distance: &f64,
-    ) -> Option<Vec<(usize, usize, f64)>> {
+        top_k: Option<usize>,
+    ) -> Option<MatchCollector> {
         // Skip all comparisons if string is NA
         if k1.is_na() {
             return None;
         }
 
         // Get range of lengths within max distance of current
-        let k1_len = k1.len();
+        let k1_len = k1.chars().count();
         let start_len = k1_len.saturating_sub(*max_distance as usize);
         let end_len = k1_len.saturating_add(*max_distance as usize + 1);
 
-        // Start a list to collect results
-        let mut idxs: Vec<(usize, usize, f64)> = Vec::new();
+        // Collect all matches, or only the best `top_k`
+        let mut matches = MatchCollector::new(*max_distance, top_k);
 
         // Begin making string comparisons
         for i in start_len..end_len {
+            // Skip lengths that can no longer beat the current threshold
+            if i.abs_diff(k1_len) as f64 > matches.threshold() {
+                continue;
+            }
+
             if let Some(lookup) = length_map.get(&i) {
+                if recording() {
+                    compared(lookup.iter().filter(|k2| !k2.is_na()).count());
+                }
                 lookup.iter().for_each(|k2| {
                     // Skip this iter if RHS is NA
                     if k2.is_na() {
@@ -86,33 +96,23 @@ impl EditDistance for LCSStr {
 
                     // No need to run distance functions if exactly the same
                     if &k1 == k2 {
-                        let v2 = idx_map.get(k2).unwrap();
-                        iproduct!(v1, v2).for_each(|(v1, v2)| {
-                            idxs.push((*v1, *v2, 0.));
-                        });
+                        matches.push(idx_map.get(k2).unwrap(), 0.);
                         return;
                     }
 
                     // Run distance calculation
+                    let threshold = matches.threshold();
                     let dist = self.compute(&k1, &k2) as f64;
 
                     // Check vs. threshold
-                    if dist <= *max_distance {
-                        let v2 = idx_map.get(k2).unwrap();
-                        iproduct!(v1, v2).for_each(|(v1, v2)| {
-                            idxs.push((*v1, *v2, dist));
-                        });
-                        return;
+                    if dist <= threshold {
+                        matches.push(idx_map.get(k2).unwrap(), dist);
                     }
                 });
             }
         }
 
         // Return all matches, if any
-        if idxs.is_empty() {
-            return None;
-        } else {
-            return Some(idxs);
-        }
+        Some(matches)
     }
 }
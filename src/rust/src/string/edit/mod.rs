@@ -1,7 +1,7 @@
-use crate::utils::robj_index_map;
-use extendr_api::prelude::*;
+use crate::explain::busy_timer;
+use crate::progress::{install, SearchPool};
+use crate::string::topk::{MatchCollector, MatchSink};
 use rayon::iter::*;
-use rayon::ThreadPool;
 use rustc_hash::FxHashMap;
 
 pub mod damerau_levenshtein;
@@ -17,45 +17,45 @@ pub trait EditDistance: Send + Sync {
         left: &Vec<&str>,
         right: &Vec<&str>,
         max_distance: &f64,
-        pool: &rayon::ThreadPool,
+        pool: &SearchPool,
     ) -> (Vec<usize>, Vec<f64>);
 
-    fn fuzzy_indices(
+    fn fuzzy_indices<S: MatchSink>(
         &self,
-        left: &List,
-        left_key: &str,
-        right: &List,
-        right_key: &str,
+        map1: &FxHashMap<&str, Vec<usize>>,
+        map2: &FxHashMap<&str, Vec<usize>>,
         max_distance: f64,
-        pool: &ThreadPool,
-    ) -> anyhow::Result<Vec<(usize, usize, f64)>> {
-        let map1 = robj_index_map(left, left_key)?;
-        let map2 = robj_index_map(right, right_key)?;
-
+        top_k: Option<usize>,
+        sink: &S,
+        pool: &SearchPool,
+    ) -> anyhow::Result<Vec<S::Item>> {
+        // Values by number of characters, as an edit changes it by at most one
         let mut length_map: FxHashMap<usize, Vec<&str>> = FxHashMap::default();
         for key in map2.keys() {
-            let key_len = key.len();
+            let key_len = key.chars().count();
             length_map.entry(key_len).or_default().push(key);
         }
 
-        let idxs: Vec<(usize, usize, f64)> = pool.install(|| {
+        install(pool, map1.len(), || {
             map1.par_iter()
+                .filter(|_| pool.tick())
                 .filter_map(|(k1, v1)| {
-                    self.compare_one_to_many(k1, v1, &length_map, &map2, &max_distance)
+                    let _busy = busy_timer();
+                    let matches =
+                        self.compare_one_to_many(k1, &length_map, map2, &max_distance, top_k)?;
+                    Some(sink.take(v1, matches))
                 })
                 .flatten()
                 .collect()
-        });
-
-        Ok(idxs)
+        })
     }
 
     fn compare_one_to_many(
         &self,
         k1: &str,
-        v1: &Vec<usize>,
         length_map: &FxHashMap<usize, Vec<&str>>,
         idx_map: &FxHashMap<&str, Vec<usize>>,
         max_distance: &f64,
-    ) -> Option<Vec<(usize, usize, f64)>>;
+        top_k: Option<usize>,
+    ) -> Option<MatchCollector>;
 }
@@ -1,37 +1,55 @@
-use crate::utils::robj_index_map;
+use crate::explain::busy_timer;
+use crate::progress::{install, SearchPool};
+use crate::string::topk::{MatchCollector, MatchSink};
 use anyhow::Result;
 use extendr_api::prelude::*;
-use itertools::iproduct;
 use rapidfuzz::distance::jaro as jaro_rf;
 use rayon::prelude::*;
-use rayon::ThreadPool;
 use rustc_hash::FxHashMap;
 
 pub struct JaroWinkler;
 impl JaroWinkler {
-    pub fn fuzzy_indices(
+    /// Jaro-Winkler distance of a single pair, without a cutoff.
+    pub fn compute(&self, l: &str, r: &str, prefix_weight: f64, max_prefix: usize) -> f64 {
+        let x = jaro_rf::distance(l.chars(), r.chars());
+        let capped_prefix_len = l
+            .chars()
+            .zip(r.chars())
+            .take_while(|(c1, c2)| c1 == c2)
+            .count()
+            .min(max_prefix);
+        x + capped_prefix_len as f64 * prefix_weight * (1.0 - x)
+    }
+
+    pub fn fuzzy_indices<S: MatchSink>(
         &self,
-        df1: &List,
-        left_key: &str,
-        df2: &List,
-        right_key: &str,
+        map1: &FxHashMap<&str, Vec<usize>>,
+        map2: &FxHashMap<&str, Vec<usize>>,
         max_distance: f64,
         prefix_weight: f64,
         max_prefix: usize,
-        pool: &ThreadPool,
-    ) -> Result<Vec<(usize, usize, f64)>> {
-        let map1 = robj_index_map(&df1, left_key)?;
-        let map2 = robj_index_map(&df2, right_key)?;
-
-        let idxs: Vec<(usize, usize, f64)> = pool.install(|| {
+        top_k: Option<usize>,
+        sink: &S,
+        pool: &SearchPool,
+    ) -> Result<Vec<S::Item>> {
+        install(pool, map1.len(), || {
             map1.par_iter()
+                .filter(|_| pool.tick())
                 .filter_map(|(k1, v1)| {
-                    self.compare_one_to_many(k1, v1, &map2, max_distance, prefix_weight, max_prefix)
+                    let _busy = busy_timer();
+                    let matches = self.compare_one_to_many(
+                        k1,
+                        map2,
+                        max_distance,
+                        prefix_weight,
+                        max_prefix,
+                        top_k,
+                    )?;
+                    Some(sink.take(v1, matches))
                 })
                 .flatten()
                 .collect()
-        });
-        Ok(idxs)
+        })
     }
 
     pub fn compare_pairs(
@@ -41,7 +59,7 @@ impl JaroWinkler {
         max_distance: &f64,
         prefix_weight: f64,
         max_prefix: usize,
-        pool: &rayon::ThreadPool,
+        pool: &SearchPool,
     ) -> (Vec<usize>, Vec<f64>) {
         let args = jaro_rf::Args::default().score_cutoff(*max_distance);
         let (keep, dists): (Vec<usize>, Vec<f64>) = pool.install(|| {
@@ -83,17 +101,17 @@ impl JaroWinkler {
     fn compare_one_to_many(
         &self,
         k1: &str,
-        v1: &Vec<usize>,
         idx_map: &FxHashMap<&str, Vec<usize>>,
         max_distance: f64,
         prefix_weight: f64,
         max_prefix: usize,
-    ) -> Option<Vec<(usize, usize, f64)>> {
+        top_k: Option<usize>,
+    ) -> Option<MatchCollector> {
         if k1.is_na() {
             return None;
         }
 
-        let mut idxs: Vec<(usize, usize, f64)> = Vec::new();
+        let mut matches = MatchCollector::new(max_distance, top_k);
 
         for (k2, v2) in idx_map.iter() {
             if k2.is_na() {
@@ -101,9 +119,7 @@ impl JaroWinkler {
             }
 
             if &k1 == k2 {
-                iproduct!(v1, v2).for_each(|(v1, v2)| {
-                    idxs.push((*v1, *v2, 0.));
-                });
+                matches.push(v2, 0.);
                 continue;
             }
 
@@ -115,27 +131,22 @@ impl JaroWinkler {
                 .count()
                 .min(max_prefix);
 
+            let threshold = matches.threshold();
             let scorer = jaro_rf::BatchComparator::new(k1.chars());
-            let args = jaro_rf::Args::default().score_cutoff(max_distance);
+            let args = jaro_rf::Args::default().score_cutoff(threshold);
 
             let dist = scorer.distance_with_args(k2.chars(), &args);
             match dist {
                 Some(x) => {
                     let x2 = x + (capped_prefix_len as f64 * prefix_weight * (1.0 - x)) as f64;
-                    if x2 <= max_distance {
-                        iproduct!(v1, v2).for_each(|(a, b)| {
-                            idxs.push((*a, *b, x2));
-                        });
+                    if x2 <= threshold {
+                        matches.push(v2, x2);
                     }
                 }
                 None => (),
             }
         }
 
-        if idxs.is_empty() {
-            None
-        } else {
-            Some(idxs)
-        }
+        Some(matches)
     }
 }
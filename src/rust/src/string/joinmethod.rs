@@ -1,3 +1,6 @@
+// Row indices of each distinct value of a string column
+type IndexMap<'a> = rustc_hash::FxHashMap<&'a str, Vec<usize>>;
+
 pub enum JoinMethod {
     OSA {
         max_distance: f64,
@@ -34,6 +37,16 @@ pub enum JoinMethod {
     Soundex {},
 }
 
+// Number of distinct values `map_indices` searches for, on the side it does
+// not index
+fn searched_len(map1: &IndexMap, map2: &IndexMap, top_k: Option<usize>) -> usize {
+    if top_k.is_none() && map2.len() > map1.len() {
+        map2.len()
+    } else {
+        map1.len()
+    }
+}
+
 impl JoinMethod {
     pub fn fuzzy_indices(
         &self,
@@ -41,53 +54,211 @@ impl JoinMethod {
         left_key: &str,
         right: &extendr_api::List,
         right_key: &str,
-        pool: &rayon::ThreadPool,
+        top_k: Option<usize>,
+        pool: &crate::progress::SearchPool,
     ) -> anyhow::Result<Vec<(usize, usize, f64)>> {
+        use crate::utils::robj_index_map;
+
+        let map1 = robj_index_map(left, left_key)?;
+        let map2 = robj_index_map(right, right_key)?;
+        self.map_indices(&map1, &map2, top_k, pool)
+    }
+
+    /// Like `fuzzy_indices`, but only compares rows within the same block.
+    /// Blocks hold the 1-based rows of each side and are searched in
+    /// parallel.
+    pub fn fuzzy_indices_blocked(
+        &self,
+        left: &extendr_api::List,
+        left_key: &str,
+        right: &extendr_api::List,
+        right_key: &str,
+        blocks: &[(Vec<usize>, Vec<usize>)],
+        top_k: Option<usize>,
+        pool: &crate::progress::SearchPool,
+    ) -> anyhow::Result<Vec<(usize, usize, f64)>> {
+        use crate::utils::subset_index_map;
+        use extendr_api::prelude::*;
+
+        let str_column = |df: &List, key: &str, side: &str| {
+            df.dollar(key)
+                .map_err(|_| anyhow::anyhow!("Missing column '{}' in {}", key, side))
+        };
+        let col1_binding = str_column(left, left_key, "df1")?;
+        let col1: Vec<&str> = col1_binding
+            .as_str_vector()
+            .ok_or_else(|| anyhow::anyhow!("Column {left_key} does not exist or is not string."))?;
+        let col2_binding = str_column(right, right_key, "df2")?;
+        let col2: Vec<&str> = col2_binding.as_str_vector().ok_or_else(|| {
+            anyhow::anyhow!("Column {right_key} does not exist or is not string.")
+        })?;
+
+        let maps: Vec<_> = blocks
+            .iter()
+            .map(|(rows1, rows2)| {
+                (
+                    subset_index_map(&col1, rows1),
+                    subset_index_map(&col2, rows2),
+                )
+            })
+            .collect();
+
+        let pairs: Vec<_> = maps.iter().map(|(map1, map2)| (map1, map2)).collect();
+        let idxs = self.map_pairs_indices(&pairs, top_k, pool)?;
+        Ok(idxs.into_iter().flatten().collect())
+    }
+
+    /// Match a column against itself, returning each unordered pair of
+    /// distinct rows once as `(i, j, dist)` with `i < j`. Distinct values are
+    /// split into chunks and only chunk pairs `a <= b` are searched, so
+    /// symmetric comparisons are mostly skipped.
+    pub fn fuzzy_indices_self(
+        &self,
+        df: &extendr_api::List,
+        key: &str,
+        pool: &crate::progress::SearchPool,
+    ) -> anyhow::Result<Vec<(usize, usize, f64)>> {
+        use crate::utils::robj_index_map;
+
+        const CHUNKS: usize = 32;
+
+        let map = robj_index_map(df, key)?;
+        let mut values: Vec<&str> = map.keys().copied().collect();
+        values.sort_unstable();
+        let chunk_size = values.len().div_ceil(CHUNKS).max(1);
+        let chunks: Vec<IndexMap> = values
+            .chunks(chunk_size)
+            .map(|chunk| chunk.iter().map(|&v| (v, map[v].clone())).collect())
+            .collect();
+
+        let mut pairs = Vec::new();
+        let mut diagonal = Vec::new();
+        for a in 0..chunks.len() {
+            for b in a..chunks.len() {
+                pairs.push((&chunks[a], &chunks[b]));
+                diagonal.push(a == b);
+            }
+        }
+
+        // Within a chunk both orders of each pair are found, so keep one.
+        // Across chunks each pair is found once, in either order.
+        let idxs = self.map_pairs_indices(&pairs, None, pool)?;
+        Ok(idxs
+            .into_iter()
+            .zip(diagonal)
+            .flat_map(|(idxs, diagonal)| {
+                idxs.into_iter().filter_map(move |(i, j, d)| {
+                    if i < j {
+                        Some((i, j, d))
+                    } else if i > j && !diagonal {
+                        Some((j, i, d))
+                    } else {
+                        None
+                    }
+                })
+            })
+            .collect())
+    }
+
+    // Search several pairs of index maps in parallel
+    fn map_pairs_indices(
+        &self,
+        pairs: &[(&IndexMap, &IndexMap)],
+        top_k: Option<usize>,
+        pool: &crate::progress::SearchPool,
+    ) -> anyhow::Result<Vec<Vec<(usize, usize, f64)>>> {
+        use crate::progress::install;
+        use rayon::prelude::*;
+
+        let total = pairs
+            .iter()
+            .map(|(map1, map2)| searched_len(map1, map2, top_k))
+            .sum();
+        install(pool, total, || {
+            pairs
+                .par_iter()
+                .map(|(map1, map2)| self.map_indices(map1, map2, top_k, pool))
+                .collect()
+        })?
+    }
+
+    // The methods index `map2` and search it for each value of `map1` in
+    // parallel, so the side with fewer distinct values is indexed. All
+    // methods are symmetric, but a `top_k` per left row needs `map1` on the
+    // left.
+    pub fn map_indices(
+        &self,
+        map1: &IndexMap,
+        map2: &IndexMap,
+        top_k: Option<usize>,
+        pool: &crate::progress::SearchPool,
+    ) -> anyhow::Result<Vec<(usize, usize, f64)>> {
+        if top_k.is_none() && map2.len() > map1.len() {
+            let idxs = self.search(map2, map1, None, &crate::string::topk::RowPairs(pool), pool)?;
+            return Ok(idxs.into_iter().map(|(j, i, d)| (i, j, d)).collect());
+        }
+        self.search(
+            map1,
+            map2,
+            top_k,
+            &crate::string::topk::RowPairs(pool),
+            pool,
+        )
+    }
+
+    /// Search `map2` for the matches of each value of `map1`, which `sink`
+    /// turns into the items returned as soon as each value is searched.
+    pub fn search<S: crate::string::topk::MatchSink>(
+        &self,
+        map1: &IndexMap,
+        map2: &IndexMap,
+        top_k: Option<usize>,
+        sink: &S,
+        pool: &crate::progress::SearchPool,
+    ) -> anyhow::Result<Vec<S::Item>> {
         use crate::string::*;
 
-        let result =
-            match self {
-                JoinMethod::OSA { max_distance } => {
-                    OSA.fuzzy_indices(left, left_key, right, right_key, *max_distance, pool)
-                }
-                JoinMethod::Levenshtein { max_distance } => {
-                    Levenshtein.fuzzy_indices(left, left_key, right, right_key, *max_distance, pool)
-                }
-                JoinMethod::DamerauLevenshtein { max_distance } => DamerauLevenshtein
-                    .fuzzy_indices(left, left_key, right, right_key, *max_distance, pool),
-                JoinMethod::Hamming { max_distance } => {
-                    Hamming.fuzzy_indices(left, left_key, right, right_key, *max_distance, pool)
-                }
-                JoinMethod::LCS { max_distance } => {
-                    LCSStr.fuzzy_indices(left, left_key, right, right_key, *max_distance, pool)
-                }
-                JoinMethod::QGram { max_distance, q } => {
-                    QGram.fuzzy_indices(left, left_key, right, right_key, *max_distance, *q, pool)
-                }
-                JoinMethod::Cosine { max_distance, q } => {
-                    Cosine.fuzzy_indices(left, left_key, right, right_key, *max_distance, *q, pool)
-                }
-                JoinMethod::Jaccard { max_distance, q } => {
-                    Jaccard.fuzzy_indices(left, left_key, right, right_key, *max_distance, *q, pool)
-                }
-                JoinMethod::JaroWinkler {
-                    max_distance,
-                    prefix_weight,
-                    max_prefix,
-                } => JaroWinkler.fuzzy_indices(
-                    left,
-                    left_key,
-                    right,
-                    right_key,
-                    *max_distance,
-                    *prefix_weight,
-                    *max_prefix,
-                    pool,
-                ),
-                JoinMethod::Soundex {} => {
-                    Soundex.fuzzy_indices(left, left_key, right, right_key, pool)
-                }
-            }?;
+        let result = match self {
+            JoinMethod::OSA { max_distance } => {
+                OSA.fuzzy_indices(map1, map2, *max_distance, top_k, sink, pool)
+            }
+            JoinMethod::Levenshtein { max_distance } => {
+                Levenshtein.fuzzy_indices(map1, map2, *max_distance, top_k, sink, pool)
+            }
+            JoinMethod::DamerauLevenshtein { max_distance } => {
+                DamerauLevenshtein.fuzzy_indices(map1, map2, *max_distance, top_k, sink, pool)
+            }
+            JoinMethod::Hamming { max_distance } => {
+                Hamming.fuzzy_indices(map1, map2, *max_distance, top_k, sink, pool)
+            }
+            JoinMethod::LCS { max_distance } => {
+                LCSStr.fuzzy_indices(map1, map2, *max_distance, top_k, sink, pool)
+            }
+            JoinMethod::QGram { max_distance, q } => {
+                QGram.fuzzy_indices(map1, map2, *max_distance, *q, top_k, sink, pool)
+            }
+            JoinMethod::Cosine { max_distance, q } => {
+                Cosine.fuzzy_indices(map1, map2, *max_distance, *q, top_k, sink, pool)
+            }
+            JoinMethod::Jaccard { max_distance, q } => {
+                Jaccard.fuzzy_indices(map1, map2, *max_distance, *q, top_k, sink, pool)
+            }
+            JoinMethod::JaroWinkler {
+                max_distance,
+                prefix_weight,
+                max_prefix,
+            } => JaroWinkler.fuzzy_indices(
+                map1,
+                map2,
+                *max_distance,
+                *prefix_weight,
+                *max_prefix,
+                top_k,
+                sink,
+                pool,
+            ),
+            JoinMethod::Soundex {} => Soundex.fuzzy_indices(map1, map2, top_k, sink, pool),
+        }?;
 
         Ok(result)
     }
@@ -96,7 +267,7 @@ impl JoinMethod {
         &self,
         left: &Vec<&str>,
         right: &Vec<&str>,
-        pool: &rayon::ThreadPool,
+        pool: &crate::progress::SearchPool,
     ) -> anyhow::Result<(Vec<usize>, Vec<f64>)> {
         use crate::string::*;
 
@@ -144,6 +315,159 @@ impl JoinMethod {
     }
 }
 
+impl JoinMethod {
+    /// Name of the method's candidate filter, the number of pairs of
+    /// distinct non-missing values, and how many of them the filter leaves to
+    /// compare: values within the length window for edit distances, and
+    /// values sharing a q-gram for Jaccard, as counted by the search that
+    /// just ran. Other methods compare every pair. Searches keeping only the
+    /// `top_k` best matches skip length windows that can no longer win, and
+    /// do not count them.
+    pub fn filter_counts(&self, map1: &IndexMap, map2: &IndexMap) -> (&'static str, usize, usize) {
+        use crate::explain::compared_count;
+        use extendr_api::prelude::*;
+
+        let vals1: Vec<&str> = map1.keys().filter(|k| !k.is_na()).copied().collect();
+        let vals2: Vec<&str> = map2.keys().filter(|k| !k.is_na()).copied().collect();
+        let possible = vals1.len() * vals2.len();
+
+        match self {
+            JoinMethod::OSA { .. }
+            | JoinMethod::Levenshtein { .. }
+            | JoinMethod::DamerauLevenshtein { .. }
+            | JoinMethod::Hamming { .. }
+            | JoinMethod::LCS { .. } => ("length window", possible, compared_count()),
+            JoinMethod::Jaccard { .. } => ("shared q-grams", possible, compared_count()),
+            _ => ("none", possible, possible),
+        }
+    }
+
+    /// Distance between a single pair of strings, without any cutoff. Returns
+    /// `None` if the pair cannot be compared: either string is `NA`, or the
+    /// strings differ in length under Hamming distance.
+    pub fn distance(&self, a: &str, b: &str) -> Option<f64> {
+        use crate::string::*;
+        use crate::utils::get_qgrams;
+        use rapidfuzz::distance::{damerau_levenshtein, hamming, levenshtein, osa};
+
+        if a.is_na() || b.is_na() {
+            return None;
+        }
+
+        let dist = match self {
+            JoinMethod::OSA { .. } => osa::distance(a.chars(), b.chars()) as f64,
+            JoinMethod::Levenshtein { .. } => levenshtein::distance(a.chars(), b.chars()) as f64,
+            JoinMethod::DamerauLevenshtein { .. } => {
+                damerau_levenshtein::distance(a.chars(), b.chars()) as f64
+            }
+            JoinMethod::Hamming { .. } => hamming::distance(a.chars(), b.chars()).ok()? as f64,
+            JoinMethod::LCS { .. } => LCSStr.compute(a, b) as f64,
+            JoinMethod::QGram { q, .. } => QGram.compute(&get_qgrams(a, *q), &get_qgrams(b, *q)),
+            JoinMethod::Cosine { q, .. } => Cosine.compute(&get_qgrams(a, *q), &get_qgrams(b, *q)),
+            JoinMethod::Jaccard { q, .. } => {
+                Jaccard.compute(&get_qgrams(a, *q), &get_qgrams(b, *q))
+            }
+            JoinMethod::JaroWinkler {
+                prefix_weight,
+                max_prefix,
+                ..
+            } => JaroWinkler.compute(a, b, *prefix_weight, *max_prefix),
+            JoinMethod::Soundex {} => {
+                if Soundex.codes_match(a, b) {
+                    0.
+                } else {
+                    1.
+                }
+            }
+        };
+
+        Some(dist)
+    }
+
+    /// The same method with the raw cutoff that keeps every pair of values
+    /// of `left` and `right` whose `normalized_distance` is at most `ratio`.
+    pub fn normalized_cutoff(&self, ratio: f64, left: &[&str], right: &[&str]) -> JoinMethod {
+        use crate::utils::get_qgrams;
+
+        let longest = |values: &[&str], size: &dyn Fn(&str) -> usize| {
+            values.iter().map(|v| size(v)).max().unwrap_or(0) as f64
+        };
+        let chars = |s: &str| s.chars().count();
+
+        let max_distance = match self {
+            JoinMethod::OSA { .. }
+            | JoinMethod::Levenshtein { .. }
+            | JoinMethod::DamerauLevenshtein { .. }
+            | JoinMethod::Hamming { .. } => {
+                ratio * longest(left, &chars).max(longest(right, &chars))
+            }
+            JoinMethod::LCS { .. } => ratio * (longest(left, &chars) + longest(right, &chars)),
+            JoinMethod::QGram { q, .. } => {
+                let grams = |s: &str| get_qgrams(s, *q).values().sum::<usize>();
+                ratio * (longest(left, &grams) + longest(right, &grams))
+            }
+            _ => ratio,
+        };
+        self.with_max_distance(max_distance)
+    }
+
+    fn with_max_distance(&self, max_distance: f64) -> JoinMethod {
+        match *self {
+            JoinMethod::OSA { .. } => JoinMethod::OSA { max_distance },
+            JoinMethod::Levenshtein { .. } => JoinMethod::Levenshtein { max_distance },
+            JoinMethod::DamerauLevenshtein { .. } => {
+                JoinMethod::DamerauLevenshtein { max_distance }
+            }
+            JoinMethod::Hamming { .. } => JoinMethod::Hamming { max_distance },
+            JoinMethod::LCS { .. } => JoinMethod::LCS { max_distance },
+            JoinMethod::QGram { q, .. } => JoinMethod::QGram { max_distance, q },
+            JoinMethod::Cosine { q, .. } => JoinMethod::Cosine { max_distance, q },
+            JoinMethod::Jaccard { q, .. } => JoinMethod::Jaccard { max_distance, q },
+            JoinMethod::JaroWinkler {
+                prefix_weight,
+                max_prefix,
+                ..
+            } => JoinMethod::JaroWinkler {
+                max_distance,
+                prefix_weight,
+                max_prefix,
+            },
+            JoinMethod::Soundex {} => JoinMethod::Soundex {},
+        }
+    }
+
+    /// Distance between a single pair of strings rescaled to `[0, 1]`, where
+    /// 0 means identical and 1 means complete disagreement. Edit distances
+    /// are divided by the largest distance possible for the pair's lengths.
+    /// Pairs that cannot be compared score 1.
+    pub fn normalized_distance(&self, a: &str, b: &str) -> f64 {
+        use crate::utils::get_qgrams;
+
+        let Some(dist) = self.distance(a, b) else {
+            return 1.;
+        };
+
+        let max_possible = match self {
+            JoinMethod::OSA { .. }
+            | JoinMethod::Levenshtein { .. }
+            | JoinMethod::DamerauLevenshtein { .. }
+            | JoinMethod::Hamming { .. } => a.chars().count().max(b.chars().count()) as f64,
+            JoinMethod::LCS { .. } => (a.chars().count() + b.chars().count()) as f64,
+            JoinMethod::QGram { q, .. } => {
+                let total = |s: &str| get_qgrams(s, *q).values().sum::<usize>();
+                (total(a) + total(b)) as f64
+            }
+            _ => 1.,
+        };
+
+        if max_possible > 0. {
+            (dist / max_possible).clamp(0., 1.)
+        } else {
+            0.
+        }
+    }
+}
+
 pub fn get_join_method(
     method: &str,
     max_distance: f64,
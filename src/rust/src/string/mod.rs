@@ -1,24 +1,34 @@
 pub mod edit;
 pub mod jaro_winkler;
 pub mod joinmethod;
+pub mod neighborhood;
 pub mod ngram;
+pub mod rule;
 pub mod soundex;
+pub mod topk;
 
-use crate::merge::{dispatch_join, DistanceData};
+use crate::assignment::{assignment_positions, Assignment};
+use crate::explain::{mark, Explain};
+use crate::merge::{dispatch_join, DistanceData, OutputSpec};
+use crate::planner::{distinct_count, key_order, nrow, restore_order, sample_pairs, selectivity};
+use crate::progress::SearchPool;
 use crate::string::edit::{
     damerau_levenshtein::DamerauLevenshtein, hamming::Hamming, lcs::LCSStr,
     levenshtein::Levenshtein, osa::OSA, EditDistance,
 };
 use crate::string::jaro_winkler::JaroWinkler;
-use crate::string::joinmethod::get_join_method;
+use crate::string::joinmethod::{get_join_method, JoinMethod};
+use crate::string::neighborhood::{sort_orders, window_pairs};
 use crate::string::ngram::{cosine::Cosine, jaccard::Jaccard, qgram::QGram, QGramDistance};
 use crate::string::soundex::Soundex;
-use crate::utils::get_pool;
+use crate::string::topk::{top_k_positions, TopKBy};
+use crate::utils::{block_rows, get_pool, robj_index_map, subset_pairs, summed_distances};
 
 use anyhow::{anyhow, Result};
 use extendr_api::prelude::*;
+use rayon::prelude::*;
 
-fn extract_keys(by: &List) -> Result<Vec<(String, String)>> {
+pub fn extract_keys(by: &List) -> Result<Vec<(String, String)>> {
     by.iter()
         .map(|(left_key, val)| {
             let right_keys = val
@@ -34,41 +44,336 @@ fn extract_keys(by: &List) -> Result<Vec<(String, String)>> {
         .collect()
 }
 
+/// Build one `JoinMethod` per join key from per-key settings. Settings are
+/// recycled by the R wrapper, so each must have one entry per key; `NA`
+/// entries mean the setting was not provided.
+pub fn key_join_methods(
+    keys: &[(String, String)],
+    method: &[String],
+    max_distance: &[f64],
+    q: &[Rint],
+    max_prefix: &[Rint],
+    prefix_weight: &[Rfloat],
+) -> Result<Vec<JoinMethod>> {
+    let n = keys.len();
+    if [
+        method.len(),
+        max_distance.len(),
+        q.len(),
+        max_prefix.len(),
+        prefix_weight.len(),
+    ]
+    .iter()
+    .any(|&len| len != n)
+    {
+        return Err(anyhow!(
+            "Per-key settings must have one entry per join column ({})",
+            n
+        ));
+    }
+
+    let as_usize = |x: &Rint| (!x.is_na()).then(|| x.inner() as usize);
+    (0..n)
+        .map(|i| {
+            get_join_method(
+                &method[i].to_lowercase(),
+                max_distance[i],
+                as_usize(&q[i]),
+                (!prefix_weight[i].is_na()).then(|| prefix_weight[i].inner()),
+                as_usize(&max_prefix[i]),
+            )
+            .map_err(|e| anyhow!("Join column `{}`: {e}", keys[i].0))
+        })
+        .collect()
+}
+
+/// Options of a string join, parsed from the named list built by
+/// `fozzie_string_join()`. Per-key settings are recycled by the R wrapper to
+/// one entry per join column.
+pub struct StringJoinSettings {
+    pub method: Vec<String>,
+    pub max_distance: Vec<f64>,
+    pub distance_col: Option<String>,
+    pub q: Vec<Rint>,
+    pub max_prefix: Vec<Rint>,
+    pub prefix_weight: Vec<Rfloat>,
+    pub weights: Vec<Rfloat>,
+    pub max_score: Option<f64>,
+    pub top_k: Option<i32>,
+    pub top_k_by: String,
+    pub assignment: String,
+    pub block1: Option<Vec<Rint>>,
+    pub block2: Option<Vec<Rint>>,
+    pub sort_order: Option<List>,
+    pub window: i32,
+    pub explain: bool,
+}
+
+impl Default for StringJoinSettings {
+    fn default() -> Self {
+        StringJoinSettings {
+            method: Vec::new(),
+            max_distance: Vec::new(),
+            distance_col: None,
+            q: Vec::new(),
+            max_prefix: Vec::new(),
+            prefix_weight: Vec::new(),
+            weights: Vec::new(),
+            max_score: None,
+            top_k: None,
+            top_k_by: "left".to_string(),
+            assignment: "none".to_string(),
+            block1: None,
+            block2: None,
+            sort_order: None,
+            window: 10,
+            explain: false,
+        }
+    }
+}
+
+impl StringJoinSettings {
+    pub fn from_list(list: &List) -> Result<Self> {
+        let mut settings = StringJoinSettings::default();
+        for (name, value) in list.iter() {
+            let invalid = |kind: &str| anyhow!("`{}` must be {}", name, kind);
+            let integers = |value: Robj| {
+                Vec::<Rint>::try_from(value).map_err(|_| invalid("an integer vector"))
+            };
+            let number = |value: &Robj| {
+                (!value.is_null())
+                    .then(|| {
+                        value
+                            .as_real()
+                            .or_else(|| value.as_integer().map(f64::from))
+                            .ok_or_else(|| invalid("a number"))
+                    })
+                    .transpose()
+            };
+            let string = |value: &Robj| {
+                value
+                    .as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| invalid("a string"))
+            };
+            match name {
+                "method" => {
+                    settings.method = value
+                        .as_string_vector()
+                        .ok_or_else(|| invalid("a character vector"))?
+                }
+                "max_distance" => {
+                    settings.max_distance =
+                        value.as_real_vector().ok_or_else(|| invalid("numeric"))?
+                }
+                "distance_col" if value.is_null() => settings.distance_col = None,
+                "distance_col" => settings.distance_col = Some(string(&value)?),
+                "q" => settings.q = integers(value)?,
+                "max_prefix" => settings.max_prefix = integers(value)?,
+                "prefix_weight" => {
+                    settings.prefix_weight =
+                        Vec::<Rfloat>::try_from(value).map_err(|_| invalid("numeric"))?
+                }
+                "weights" => {
+                    settings.weights =
+                        Vec::<Rfloat>::try_from(value).map_err(|_| invalid("numeric"))?
+                }
+                "max_score" => settings.max_score = number(&value)?,
+                "top_k" => {
+                    settings.top_k = match number(&value)? {
+                        Some(k) if k.fract() != 0.0 => {
+                            return Err(anyhow!("`top_k` must be a positive integer"))
+                        }
+                        k => k.map(|k| k as i32),
+                    }
+                }
+                "top_k_by" => settings.top_k_by = string(&value)?,
+                "assignment" => settings.assignment = string(&value)?,
+                "block1" if value.is_null() => settings.block1 = None,
+                "block1" => settings.block1 = Some(integers(value)?),
+                "block2" if value.is_null() => settings.block2 = None,
+                "block2" => settings.block2 = Some(integers(value)?),
+                "sort_order" => {
+                    settings.sort_order = List::try_from(value)
+                        .ok()
+                        .filter(|orders| !orders.is_empty())
+                }
+                "window" => {
+                    settings.window = number(&value)?
+                        .filter(|w| w.fract() == 0.0)
+                        .ok_or_else(|| invalid("an integer"))?
+                        as i32
+                }
+                "explain" => {
+                    settings.explain = value.as_bool().ok_or_else(|| invalid("TRUE or FALSE"))?
+                }
+                _ => return Err(anyhow!("Unknown string join setting: {}", name)),
+            }
+        }
+        Ok(settings)
+    }
+}
+
 pub fn string_join(
     df1: List,
     df2: List,
     by: List,
-    method: String,
     how: String,
-    max_distance: f64,
-    distance_col: Option<String>,
-    q: Option<i32>,
-    max_prefix: Option<i32>,
-    prefix_weight: Option<f64>,
+    settings: StringJoinSettings,
+    output: OutputSpec,
     nthread: Option<usize>,
 ) -> Result<List> {
+    let StringJoinSettings {
+        method,
+        max_distance,
+        distance_col,
+        q,
+        max_prefix,
+        prefix_weight,
+        weights,
+        max_score,
+        top_k,
+        top_k_by,
+        assignment,
+        block1,
+        block2,
+        sort_order,
+        window,
+        explain,
+    } = settings;
     let keys: Vec<(String, String)> = extract_keys(&by)?;
     let pool = get_pool(nthread)?;
-    let (left_key, right_key) = &keys[0];
+    let mut explain = explain.then(Explain::start);
 
-    let qz = match q {
-        Some(x) => Some(x as usize),
-        None => None,
+    let join_methods = key_join_methods(
+        &keys,
+        &method,
+        &max_distance,
+        &q,
+        &max_prefix,
+        &prefix_weight,
+    )?;
+
+    // The most selective key generates candidates and the others are checked
+    // from most to least selective. In composite mode only the first key has
+    // a cutoff of its own, so the order is kept.
+    let order = if keys.len() > 1 && max_score.is_none() {
+        let estimates = key_selectivity(&df1, &df2, &keys, &join_methods, &pool)?;
+        mark(&mut explain, "sample", None);
+        key_order(&estimates)
+    } else {
+        (0..keys.len()).collect()
     };
+    let (left_key, right_key) = &keys[order[0]];
 
-    let max_prefix = match max_prefix {
-        Some(x) => Some(x as usize),
+    // In composite mode, a pair can only be kept if its normalized distance
+    // on the first key is at most `max_score` over that key's weight, as the
+    // other keys add to its score. The first key is searched with that
+    // cutoff instead of its own `max_distance`.
+    let weights = match max_score {
+        Some(_) => Some(composite_weights(&weights, keys.len())?),
         None => None,
     };
+    let cutoff_method;
+    let first_method = match (max_score, &weights) {
+        (Some(max_score), Some(weights)) => {
+            let ratio = max_score / weights[0];
+            if ratio >= 1. {
+                return Err(anyhow!(
+                    "`max_score` must be below the share of the first column in `weights` ({}), so that it finds candidate pairs",
+                    weights[0]
+                ));
+            }
+            let values1 = robj_index_map(&df1, left_key)?;
+            let values2 = robj_index_map(&df2, right_key)?;
+            cutoff_method = join_methods[0].normalized_cutoff(
+                // Keep pairs exactly at the threshold despite rounding
+                ratio * (1. + 1e-9),
+                &values1.keys().copied().collect::<Vec<_>>(),
+                &values2.keys().copied().collect::<Vec<_>>(),
+            );
+            &cutoff_method
+        }
+        _ => &join_methods[order[0]],
+    };
 
-    let prefix_weight = match prefix_weight {
-        Some(x) => Some(x as f64),
+    let top_k = match top_k {
+        Some(k) if k < 1 => return Err(anyhow!("`top_k` must be a positive integer")),
+        Some(k) => Some(k as usize),
         None => None,
     };
+    let top_k_by = TopKBy::new(&top_k_by)?;
+    let assignment = Assignment::new(&assignment)?;
+
+    // The candidate search can only be pruned per left row, and only when the
+    // first key decides the ranking on its own. Otherwise, rank afterwards.
+    let heap_k = if keys.len() == 1
+        && top_k_by == TopKBy::Left
+        && max_score.is_none()
+        && sort_order.is_none()
+    {
+        top_k
+    } else {
+        None
+    };
 
-    let join_method = get_join_method(&method, max_distance, qz, prefix_weight, max_prefix)?;
-    let mut matchdat = join_method.fuzzy_indices(&df1, left_key, &df2, right_key, &pool)?;
+    // With blocking keys, the first fuzzy key is only compared within blocks.
+    // With sort orders, it is only compared within a sliding window of each.
+    let mut value_maps = None;
+    let mut matchdat = match (block1, block2, sort_order) {
+        (Some(_), Some(_), Some(_)) => {
+            return Err(anyhow!(
+                "Blocking and sorted neighborhood cannot be combined"
+            ))
+        }
+        (Some(block1), Some(block2), None) => {
+            let blocks = block_rows(&block1, &block2);
+            first_method
+                .fuzzy_indices_blocked(&df1, left_key, &df2, right_key, &blocks, heap_k, &pool)?
+        }
+        (_, _, Some(sort_order)) => {
+            if window < 2 {
+                return Err(anyhow!("`window` must be at least 2"));
+            }
+            let n1 = df1.index(1).map_or(0, |col| col.len());
+            let n2 = df2.index(1).map_or(0, |col| col.len());
+            let orders = sort_orders(&sort_order, n1, n2)?;
+            let (cands1, cands2) = window_pairs(&orders, n1, window as usize);
+            let (idxs1, idxs2, dists) = difference_pairs(
+                &df1,
+                &cands1,
+                &df2,
+                &cands2,
+                &keys[order[0]],
+                &vec![],
+                first_method,
+                &pool,
+            )?;
+            idxs1
+                .into_iter()
+                .zip(idxs2)
+                .zip(&dists[0])
+                .map(|((i, j), &d)| (i, j, d))
+                .collect()
+        }
+        _ => {
+            let map1 = robj_index_map(&df1, left_key)?;
+            let map2 = robj_index_map(&df2, right_key)?;
+            mark(&mut explain, "index", None);
+            let matchdat = first_method.map_indices(&map1, &map2, heap_k, &pool)?;
+            value_maps = Some((map1, map2));
+            matchdat
+        }
+    };
     matchdat.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+    if let Some(explain) = &mut explain {
+        explain.stage("search", Some(matchdat.len()));
+        explain.key_matches(left_key, matchdat.len());
+        if let Some((map1, map2)) = &value_maps {
+            let (filter, possible, compared) = first_method.filter_counts(map1, map2);
+            explain.filter(filter, possible, compared);
+        }
+    }
 
     let mut idxs1 = Vec::with_capacity(matchdat.len());
     let mut idxs2 = Vec::with_capacity(matchdat.len());
@@ -79,93 +384,164 @@ pub fn string_join(
         idxs2.push(j);
         dists.push(d);
     }
-    let out: List = if keys.len() == 1 {
-        let dists = DistanceData::Single(&dists);
-        let joined = dispatch_join(
-            how.as_str(),
+    let mut dists = vec![dists];
+
+    // In composite mode, later keys are scored without their own cutoffs and
+    // pairs are kept by their weighted score instead
+    let mut composite: Option<Vec<f64>> = None;
+    if let (Some(max_score), Some(weights)) = (max_score, &weights) {
+        let (raw, score) = composite_scores(
             &df1,
+            &idxs1,
             &df2,
-            idxs1,
-            idxs2,
-            distance_col,
-            dists,
-            by,
-        );
-        joined
+            &idxs2,
+            &keys,
+            &join_methods,
+            weights,
+            &pool,
+        )?;
+        let keep: Vec<usize> = (0..score.len())
+            .filter(|&p| score[p] <= max_score)
+            .collect();
+        (idxs1, idxs2, dists) = subset_pairs(&keep, &idxs1, &idxs2, &raw);
+        composite = Some(keep.iter().map(|&p| score[p]).collect());
+        mark(&mut explain, "score", Some(idxs1.len()));
     } else {
-        let mut dists = vec![dists];
-
-        for bypair in keys[1..].iter() {
+        for &k in &order[1..] {
             (idxs1, idxs2, dists) = difference_pairs(
                 &df1,
                 &idxs1,
                 &df2,
                 &idxs2,
-                &bypair,
+                &keys[k],
                 &dists,
-                max_distance,
-                &method,
-                qz,
-                max_prefix,
-                prefix_weight,
+                &join_methods[k],
                 &pool,
-            )?
+            )?;
+            if let Some(explain) = &mut explain {
+                explain.stage(&format!("verify {}", keys[k].0), Some(idxs1.len()));
+                explain.key_matches(&keys[k].0, idxs1.len());
+            }
         }
+        dists = restore_order(dists, &order);
+    }
 
-        let dists = DistanceData::Matrix(&dists);
-        let joined = dispatch_join(
-            how.as_str(),
-            &df1,
-            &df2,
-            idxs1,
-            idxs2,
-            distance_col,
-            dists,
-            by,
-        );
-        joined
+    // Pairs are ranked by their composite score if there is one, and by
+    // their summed distance across keys otherwise
+    let ranking = |dists: &Vec<Vec<f64>>, composite: &Option<Vec<f64>>| match composite {
+        Some(score) => score.clone(),
+        None => summed_distances(dists),
+    };
+
+    if let (Some(k), None) = (top_k, heap_k) {
+        let keep = top_k_positions(&idxs1, &idxs2, &ranking(&dists, &composite), k, top_k_by);
+        (idxs1, idxs2, dists) = subset_pairs(&keep, &idxs1, &idxs2, &dists);
+        composite = composite.map(|score| keep.iter().map(|&p| score[p]).collect());
+        mark(&mut explain, "top_k", Some(idxs1.len()));
+    }
+
+    if let Some(assignment) = assignment {
+        let scores = ranking(&dists, &composite);
+        let keep = assignment_positions(&idxs1, &idxs2, &scores, assignment, &pool);
+        (idxs1, idxs2, dists) = subset_pairs(&keep, &idxs1, &idxs2, &dists);
+        composite = composite.map(|score| keep.iter().map(|&p| score[p]).collect());
+        mark(&mut explain, "assignment", Some(idxs1.len()));
+    }
+
+    let labels: Vec<String> = method.iter().map(|m| m.to_lowercase()).collect();
+    let per_key = per_key_distances(&dists, &labels);
+    let dists = match &composite {
+        Some(score) => DistanceData::Composite(score, Box::new(per_key)),
+        None if keys.len() == 1 => DistanceData::Single(&dists[0]),
+        None => per_key,
     };
+    let mut out = dispatch_join(
+        how.as_str(),
+        &df1,
+        &df2,
+        idxs1,
+        idxs2,
+        distance_col,
+        dists,
+        by,
+        &output,
+        &pool,
+    )?;
+
+    // The report rides along as an attribute, for the R side to pick up
+    if let Some(mut explain) = explain {
+        explain.stage("merge", Some(nrow(&out)));
+        out.set_attrib("fozzie_explain", explain.finish(pool.current_num_threads()))
+            .map_err(|e| anyhow!("{e}"))?;
+    }
     Ok(out)
 }
 
+/// Estimated selectivity of each join key, from a sample of pairs checked
+/// under the key's own cutoff.
+pub fn key_selectivity(
+    df1: &List,
+    df2: &List,
+    keys: &[(String, String)],
+    join_methods: &[JoinMethod],
+    pool: &SearchPool,
+) -> Result<Vec<f64>> {
+    let (rows1, rows2) = sample_pairs(df1, df2);
+    keys.iter()
+        .zip(join_methods)
+        .map(|(bypair, join_method)| {
+            let (hits, _, _) =
+                difference_pairs(df1, &rows1, df2, &rows2, bypair, &vec![], join_method, pool)?;
+            let distinct = distinct_count(df1, &bypair.0)?.max(distinct_count(df2, &bypair.1)?);
+            Ok(selectivity(hits.len(), rows1.len(), distinct))
+        })
+        .collect()
+}
+
+/// Distances of each join key, labeled by method when keys use different
+/// methods.
+pub fn per_key_distances<'a>(
+    dists: &'a Vec<Vec<f64>>,
+    labels: &'a Vec<String>,
+) -> DistanceData<'a> {
+    if labels.iter().all(|m| *m == labels[0]) {
+        DistanceData::Matrix(dists)
+    } else {
+        DistanceData::Labeled(dists, labels)
+    }
+}
+
+// Subset a join column to the given rows, ready for `as_str_vector()`
+pub fn slice_key(df: &List, key: &str, idxs: &[usize], side: &str) -> Result<Robj> {
+    df.dollar(key)
+        .map_err(|_| anyhow!("Missing column '{}' in {}", key, side))?
+        .slice(idxs)
+        .map_err(|_| anyhow!("Failed to slice {} column '{}'", side, key))
+}
+
 pub fn difference_pairs(
     df1: &List,
-    idxs1: &Vec<usize>,
+    idxs1: &[usize],
     df2: &List,
-    idxs2: &Vec<usize>,
+    idxs2: &[usize],
     by: &(String, String),
     dists: &Vec<Vec<f64>>,
-    max_distance: f64,
-    method: &str,
-    q: Option<usize>,
-    max_prefix: Option<usize>,
-    prefix_weight: Option<f64>,
-    pool: &rayon::ThreadPool,
+    join_method: &JoinMethod,
+    pool: &SearchPool,
 ) -> Result<(Vec<usize>, Vec<usize>, Vec<Vec<f64>>)> {
     let lk = by.0.as_str();
     let rk = by.1.as_str();
 
-    let vec1_binding = df1
-        .dollar(lk)
-        .map_err(|_| anyhow!("Missing column '{}' in df1", lk))?
-        .slice(idxs1)
-        .map_err(|_| anyhow!("Failed to slice df1 column '{}'", lk))?;
-
+    let vec1_binding = slice_key(df1, lk, idxs1, "df1")?;
     let vec1: Vec<&str> = vec1_binding
         .as_str_vector()
         .ok_or_else(|| anyhow!("Failed to convert df1 column '{}' to string vector", lk))?;
 
-    let vec2_binding = df2
-        .dollar(rk)
-        .map_err(|_| anyhow!("Missing column '{}' in df2", rk))?
-        .slice(idxs2)
-        .map_err(|_| anyhow!("Failed to slice df2 column '{}'", rk))?;
-
+    let vec2_binding = slice_key(df2, rk, idxs2, "df2")?;
     let vec2: Vec<&str> = vec2_binding
         .as_str_vector()
         .ok_or_else(|| anyhow!("Failed to convert df2 column '{}' to string vector", rk))?;
 
-    let join_method = get_join_method(method, max_distance, q, prefix_weight, max_prefix)?;
     let (idxs0, newdist) = join_method.compare_pairs(&vec1, &vec2, pool)?;
     let (idxs1b, idxs2b) = { idxs0.iter().map(|&i| (idxs1[i], idxs2[i])).unzip() };
 
@@ -177,3 +553,73 @@ pub fn difference_pairs(
     dists_out.push(newdist);
     Ok((idxs1b, idxs2b, dists_out))
 }
+
+/// Validate per-key weights (`NA` means 1) and scale them to sum to one.
+fn composite_weights(weights: &[Rfloat], n: usize) -> Result<Vec<f64>> {
+    if weights.len() != n {
+        return Err(anyhow!(
+            "`weights` must have one entry per join column ({})",
+            n
+        ));
+    }
+    let weights: Vec<f64> = weights
+        .iter()
+        .map(|w| if w.is_na() { 1. } else { w.inner() })
+        .collect();
+    if weights.iter().any(|&w| !w.is_finite() || w < 0.) {
+        return Err(anyhow!("`weights` must be non-negative numbers"));
+    }
+    let total: f64 = weights.iter().sum();
+    if total <= 0. {
+        return Err(anyhow!("At least one of `weights` must be positive"));
+    }
+    Ok(weights.iter().map(|w| w / total).collect())
+}
+
+/// Score candidate pairs on every key. Returns the raw per-key distances,
+/// one vector per key, and the weighted mean of the normalized distances.
+pub fn composite_scores(
+    df1: &List,
+    idxs1: &[usize],
+    df2: &List,
+    idxs2: &[usize],
+    keys: &[(String, String)],
+    join_methods: &[JoinMethod],
+    weights: &[f64],
+    pool: &SearchPool,
+) -> Result<(Vec<Vec<f64>>, Vec<f64>)> {
+    let mut raw = Vec::with_capacity(keys.len());
+    let mut score = vec![0.; idxs1.len()];
+
+    for (((lk, rk), join_method), weight) in keys.iter().zip(join_methods).zip(weights) {
+        let vec1_binding = slice_key(df1, lk, idxs1, "df1")?;
+        let vec1: Vec<&str> = vec1_binding
+            .as_str_vector()
+            .ok_or_else(|| anyhow!("Failed to convert df1 column '{}' to string vector", lk))?;
+
+        let vec2_binding = slice_key(df2, rk, idxs2, "df2")?;
+        let vec2: Vec<&str> = vec2_binding
+            .as_str_vector()
+            .ok_or_else(|| anyhow!("Failed to convert df2 column '{}' to string vector", rk))?;
+
+        let (dist, normalized): (Vec<f64>, Vec<f64>) = pool.install(|| {
+            vec1.par_iter()
+                .zip(&vec2)
+                .map(|(a, b)| {
+                    (
+                        join_method.distance(a, b).unwrap_or(f64::NAN),
+                        join_method.normalized_distance(a, b),
+                    )
+                })
+                .unzip()
+        });
+
+        score
+            .iter_mut()
+            .zip(normalized)
+            .for_each(|(s, d)| *s += weight * d);
+        raw.push(dist);
+    }
+
+    Ok((raw, score))
+}
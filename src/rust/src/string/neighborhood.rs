@@ -0,0 +1,47 @@
+use anyhow::{anyhow, Result};
+use extendr_api::prelude::*;
+use rustc_hash::FxHashSet;
+
+/// Parse the sort orders of each sorted-neighborhood pass. Each order lists
+/// the 1-based positions of the stacked records, left rows `1..=n1` followed
+/// by right rows, from first to last in the sort.
+pub fn sort_orders(orders: &List, n1: usize, n2: usize) -> Result<Vec<Vec<usize>>> {
+    orders
+        .values()
+        .map(|order| {
+            let order = order
+                .as_integer_slice()
+                .ok_or_else(|| anyhow!("Sort orders must be integer vectors"))?;
+            if order.len() != n1 + n2 || order.iter().any(|&p| p < 1 || p as usize > n1 + n2) {
+                return Err(anyhow!(
+                    "Each sort order must index the rows of both tables"
+                ));
+            }
+            Ok(order.iter().map(|&p| p as usize).collect())
+        })
+        .collect()
+}
+
+/// Candidate pairs of the sorted-neighborhood method. In every pass, each
+/// record is paired with the records from the other table among the next
+/// `window - 1` records of the sort. Pairs are unioned across passes and
+/// returned as sorted 1-based row indices.
+pub fn window_pairs(orders: &[Vec<usize>], n1: usize, window: usize) -> (Vec<usize>, Vec<usize>) {
+    let mut pairs: FxHashSet<(usize, usize)> = FxHashSet::default();
+
+    for order in orders {
+        for (p, &a) in order.iter().enumerate() {
+            for &b in order.iter().skip(p + 1).take(window - 1) {
+                match (a <= n1, b <= n1) {
+                    (true, false) => pairs.insert((a, b - n1)),
+                    (false, true) => pairs.insert((b, a - n1)),
+                    _ => false,
+                };
+            }
+        }
+    }
+
+    let mut pairs: Vec<(usize, usize)> = pairs.into_iter().collect();
+    pairs.sort_unstable();
+    pairs.into_iter().unzip()
+}
@@ -4,15 +4,16 @@
 
 use crate::string::ngram::QGramDistance;
 use rustc_hash::FxHashMap;
+use std::hash::Hash;
 
 // Cosine Distance Implementation
 pub struct Cosine;
 
 impl QGramDistance for Cosine {
-    fn compute(
+    fn compute<K: Eq + Hash>(
         &self,
-        qgrams_s1: &FxHashMap<&str, usize>,
-        qgrams_s2: &FxHashMap<&str, usize>,
+        qgrams_s1: &FxHashMap<K, usize>,
+        qgrams_s2: &FxHashMap<K, usize>,
     ) -> f64 {
         let mut dot_product = 0;
         let mut norm_s1 = 0;
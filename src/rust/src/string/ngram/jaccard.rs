@@ -2,19 +2,22 @@
 // Source: https://docs.rs/textdistance/latest/textdistance/
 // License: MIT
 
-use anyhow::{anyhow, Result};
+use crate::explain::{busy_timer, compared, recording};
+use crate::progress::{install, SearchPool};
+use anyhow::Result;
 use extendr_api::prelude::*;
 use rayon::prelude::*;
-use rayon::ThreadPool;
 use rustc_hash::{FxHashMap, FxHashSet};
 use std::collections::VecDeque;
+use std::hash::Hash;
 
 use crate::string::ngram::QGramDistance;
+use crate::string::topk::{MatchCollector, MatchSink};
 
 // Cosine Distance Implementation
 pub struct Jaccard;
 
-fn get_qgram_set(s: &str, q: usize) -> FxHashSet<&str> {
+pub fn get_qgram_set(s: &str, q: usize) -> FxHashSet<&str> {
     let mut grams = FxHashSet::default();
     let mut ring = VecDeque::with_capacity(q + 1);
 
@@ -38,20 +41,20 @@ fn get_qgram_set(s: &str, q: usize) -> FxHashSet<&str> {
 }
 
 impl QGramDistance for Jaccard {
-    fn compute(
+    fn compute<K: Eq + Hash>(
         &self,
-        qgrams_s1: &FxHashMap<&str, usize>,
-        qgrams_s2: &FxHashMap<&str, usize>,
+        qgrams_s1: &FxHashMap<K, usize>,
+        qgrams_s2: &FxHashMap<K, usize>,
     ) -> f64 {
         let mut intersection = 0;
         let mut union = 0;
 
-        let mut all_keys: FxHashSet<_> = qgrams_s1.keys().cloned().collect();
-        all_keys.extend(qgrams_s2.keys().cloned());
+        let mut all_keys: FxHashSet<&K> = qgrams_s1.keys().collect();
+        all_keys.extend(qgrams_s2.keys());
 
         for key in all_keys {
-            let count1 = qgrams_s1.get(&key).copied().unwrap_or(0);
-            let count2 = qgrams_s2.get(&key).copied().unwrap_or(0);
+            let count1 = qgrams_s1.get(key).copied().unwrap_or(0);
+            let count2 = qgrams_s2.get(key).copied().unwrap_or(0);
 
             intersection += count1.min(count2);
             union += count1.max(count2);
@@ -70,7 +73,7 @@ impl QGramDistance for Jaccard {
         right: &Vec<&str>,
         q: &usize,
         max_distance: &f64,
-        pool: &rayon::ThreadPool,
+        pool: &SearchPool,
     ) -> (Vec<usize>, Vec<f64>) {
         let (keep, dists): (Vec<usize>, Vec<f64>) = pool.install(|| {
             left.par_iter()
@@ -104,67 +107,55 @@ impl QGramDistance for Jaccard {
         (keep, dists)
     }
 
-    fn fuzzy_indices(
+    fn fuzzy_indices<S: MatchSink>(
         &self,
-        left: &List,
-        left_key: &str,
-        right: &List,
-        right_key: &str,
+        map1: &FxHashMap<&str, Vec<usize>>,
+        map2: &FxHashMap<&str, Vec<usize>>,
         max_distance: f64,
         q: usize,
-        pool: &ThreadPool,
-    ) -> Result<Vec<(usize, usize, f64)>> {
-        // Build RHS q-gram reverse index
-        let mut rhs_qgram_index: FxHashMap<&str, Vec<usize>> = FxHashMap::default();
-        let mut rhs_qgrams: FxHashMap<usize, FxHashSet<&str>> = FxHashMap::default();
-
-        let right_iter = right
-            .dollar(right_key)
-            .map_err(|_| anyhow!("Column '{}' not found in right dataframe", right_key))?
-            .as_str_iter()
-            .ok_or_else(|| anyhow!("Column '{}' is not a string vector", right_key))?;
-
-        for (r_idx, val) in right_iter.enumerate() {
-            let idx = r_idx + 1;
+        top_k: Option<usize>,
+        sink: &S,
+        pool: &SearchPool,
+    ) -> Result<Vec<S::Item>> {
+        // Build RHS q-gram reverse index over distinct values
+        let mut rhs_qgram_index: FxHashMap<&str, Vec<&str>> = FxHashMap::default();
+        let mut rhs_qgrams: FxHashMap<&str, FxHashSet<&str>> = FxHashMap::default();
+
+        for &val in map2.keys() {
             let grams = get_qgram_set(val, q);
-            rhs_qgrams.insert(idx, grams.clone());
-            for gram in grams {
-                rhs_qgram_index.entry(gram).or_default().push(idx);
+            for &gram in &grams {
+                rhs_qgram_index.entry(gram).or_default().push(val);
             }
+            rhs_qgrams.insert(val, grams);
         }
 
-        // Match LHS records to RHS candidates via shared q-grams
-        let left_proto = left
-            .dollar(left_key)
-            .map_err(|_| anyhow!("Column '{}' not found in left dataframe", left_key))?;
-        let left_iter = left_proto
-            .as_str_vector()
-            .ok_or_else(|| anyhow!("Column '{}' is not a string vector", left_key))?;
-
-        let results = pool.install(|| {
-            left_iter
-                .par_iter()
-                .enumerate()
-                .filter_map(|(l_idx, val)| {
-                    let lhs_idx = l_idx + 1;
+        // Match LHS values to RHS candidates via shared q-grams
+        install(pool, map1.len(), || {
+            map1.par_iter()
+                .filter(|_| pool.tick())
+                .filter_map(|(val, v1)| {
+                    let _busy = busy_timer();
                     let lhs_grams = get_qgram_set(val, q);
 
                     // Collect RHS candidates that share at least one q-gram
-                    let mut candidates = FxHashSet::default();
+                    let mut candidates: FxHashSet<&str> = FxHashSet::default();
                     for gram in &lhs_grams {
-                        if let Some(rhs_idxs) = rhs_qgram_index.get(gram) {
-                            candidates.extend(rhs_idxs);
+                        if let Some(rhs_vals) = rhs_qgram_index.get(gram) {
+                            candidates.extend(rhs_vals);
                         }
                     }
 
+                    if recording() && !val.is_na() {
+                        compared(candidates.iter().filter(|v| !v.is_na()).count());
+                    }
                     if candidates.is_empty() {
                         return None;
                     }
 
                     // Compare Jaccard distance for each candidate
-                    let mut matches = Vec::new();
-                    for &rhs_idx in &candidates {
-                        let rhs_grams = &rhs_qgrams[&rhs_idx];
+                    let mut matches = MatchCollector::new(max_distance, top_k);
+                    for rhs_val in candidates {
+                        let rhs_grams = &rhs_qgrams[rhs_val];
 
                         // Predict best-case similarity
                         let max_intersection = lhs_grams.len().min(rhs_grams.len());
@@ -172,7 +163,7 @@ impl QGramDistance for Jaccard {
                         let min_possible_distance =
                             1.0 - (max_intersection as f64 / min_union as f64);
 
-                        if min_possible_distance > max_distance {
+                        if min_possible_distance > matches.threshold() {
                             continue; // Skip: can't possibly be close enough
                         }
 
@@ -181,16 +172,14 @@ impl QGramDistance for Jaccard {
                         let union = lhs_grams.union(rhs_grams).count();
                         let dist = 1.0 - (intersection as f64 / union as f64);
 
-                        if dist <= max_distance {
-                            matches.push((lhs_idx, rhs_idx, dist));
+                        if dist <= matches.threshold() {
+                            matches.push(&map2[rhs_val], dist);
                         }
                     }
-                    Some(matches)
+                    Some(sink.take(v1, matches))
                 })
                 .flatten()
                 .collect()
-        });
-
-        Ok(results)
+        })
     }
 }
@@ -1,16 +1,20 @@
-use crate::utils::{get_qgrams, robj_index_map, strvec_to_qgram_map};
+use crate::explain::busy_timer;
+use crate::progress::{install, SearchPool};
+use crate::string::topk::{MatchCollector, MatchSink};
+use crate::utils::{get_qgrams, qgram_index_map};
 use extendr_api::prelude::*;
-use itertools::iproduct;
 use rayon::prelude::*;
-use rayon::ThreadPool;
 use rustc_hash::FxHashMap;
+use std::hash::Hash;
 pub mod cosine;
 pub mod jaccard;
 pub mod qgram;
 
 // Define a trait for string distance calculations
 pub trait QGramDistance: Send + Sync {
-    fn compute(&self, s1: &FxHashMap<&str, usize>, s2: &FxHashMap<&str, usize>) -> f64;
+    /// Distance between two q-gram profiles. Q-grams may be keyed by the
+    /// strings themselves or by any id standing in for them.
+    fn compute<K: Eq + Hash>(&self, s1: &FxHashMap<K, usize>, s2: &FxHashMap<K, usize>) -> f64;
 
     fn compare_pairs(
         &self,
@@ -18,7 +22,7 @@ pub trait QGramDistance: Send + Sync {
         right: &Vec<&str>,
         q: &usize,
         max_distance: &f64,
-        pool: &rayon::ThreadPool,
+        pool: &SearchPool,
     ) -> (Vec<usize>, Vec<f64>) {
         let (keep, dists): (Vec<usize>, Vec<f64>) = pool.install(|| {
             left.par_iter()
@@ -41,69 +45,61 @@ pub trait QGramDistance: Send + Sync {
         });
         (keep, dists)
     }
-    fn fuzzy_indices(
+    fn fuzzy_indices<S: MatchSink>(
         &self,
-        left: &List,
-        left_key: &str,
-        right: &List,
-        right_key: &str,
+        map1: &FxHashMap<&str, Vec<usize>>,
+        map2: &FxHashMap<&str, Vec<usize>>,
         max_distance: f64,
         q: usize,
-        pool: &ThreadPool,
-    ) -> anyhow::Result<Vec<(usize, usize, f64)>> {
-        let map1 = robj_index_map(&left, &left_key)?;
-
+        top_k: Option<usize>,
+        sink: &S,
+        pool: &SearchPool,
+    ) -> anyhow::Result<Vec<S::Item>> {
         // This map uses qgrams as keys and keeps track of both frequencies
         // and the number of occurrences of each qgram
-        let map2_qgrams = strvec_to_qgram_map(right, right_key, q)?;
+        let map2_qgrams = qgram_index_map(map2, q);
 
-        let idxs: Vec<(usize, usize, f64)> = pool.install(|| {
+        install(pool, map1.len(), || {
             map1.par_iter()
+                .filter(|_| pool.tick())
                 .filter_map(|(k1, v1)| {
-                    let out = self.compare_one_to_many(k1, v1, &map2_qgrams, q, max_distance);
-                    out
+                    let _busy = busy_timer();
+                    let matches =
+                        self.compare_one_to_many(k1, &map2_qgrams, q, max_distance, top_k)?;
+                    Some(sink.take(v1, matches))
                 })
                 .flatten()
                 .collect()
-        });
-        Ok(idxs)
+        })
     }
 
     fn compare_one_to_many(
         &self,
         k1: &str,
-        v1: &Vec<usize>,
         map2_qgrams: &FxHashMap<&str, (FxHashMap<&str, usize>, Vec<usize>)>,
         q: usize,
         max_distance: f64,
-    ) -> Option<Vec<(usize, usize, f64)>> {
+        top_k: Option<usize>,
+    ) -> Option<MatchCollector> {
         if k1.is_na() {
             return None;
         }
 
-        let mut idxs: Vec<(usize, usize, f64)> = Vec::new();
+        let mut matches = MatchCollector::new(max_distance, top_k);
         let qg1 = get_qgrams(k1, q);
 
         for (k2, (qg2, v2)) in map2_qgrams.iter() {
             if &k1 == k2 {
-                iproduct!(v1, v2).for_each(|(v1, v2)| {
-                    idxs.push((*v1, *v2, 0.));
-                });
+                matches.push(v2, 0.);
                 continue;
             }
 
             let dist = self.compute(&qg1, &qg2) as f64;
-            if dist <= max_distance {
-                iproduct!(v1, v2).for_each(|(a, b)| {
-                    idxs.push((*a, *b, dist));
-                });
+            if dist <= matches.threshold() {
+                matches.push(v2, dist);
             }
         }
 
-        if idxs.is_empty() {
-            None
-        } else {
-            Some(idxs)
-        }
+        Some(matches)
     }
 }
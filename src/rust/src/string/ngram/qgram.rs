@@ -4,15 +4,16 @@
 
 use crate::string::ngram::QGramDistance;
 use rustc_hash::FxHashMap;
+use std::hash::Hash;
 
 // Q-Gram Distance Implementation
 pub struct QGram;
 
 impl QGramDistance for QGram {
-    fn compute(
+    fn compute<K: Eq + Hash>(
         &self,
-        qgrams_s1: &FxHashMap<&str, usize>,
-        qgrams_s2: &FxHashMap<&str, usize>,
+        qgrams_s1: &FxHashMap<K, usize>,
+        qgrams_s2: &FxHashMap<K, usize>,
     ) -> f64 {
         let mut mismatch_count = 0;
 
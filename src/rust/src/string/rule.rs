@@ -0,0 +1,144 @@
+use crate::planner::{key_order, restore_order};
+use crate::progress::SearchPool;
+use crate::string::joinmethod::JoinMethod;
+use crate::string::{difference_pairs, extract_keys, key_join_methods, key_selectivity, slice_key};
+use anyhow::{anyhow, Result};
+use extendr_api::prelude::*;
+
+/// Matched pairs, with one distance vector per key
+pub type KeyMatches = (Vec<usize>, Vec<usize>, Vec<Vec<f64>>);
+
+/// String conditions on one or more join keys, all of which must hold.
+pub struct StringRule {
+    pub keys: Vec<(String, String)>,
+    /// Method name of each key, used to label distance columns
+    pub labels: Vec<String>,
+    pub join_methods: Vec<JoinMethod>,
+}
+
+impl StringRule {
+    /// Parse a rule built by the R wrapper, with per-key settings already
+    /// recycled to one entry per join column.
+    pub fn new(rule: &Robj) -> Result<Self> {
+        let field = |name: &str| {
+            rule.dollar(name)
+                .map_err(|_| anyhow!("Rule is missing `{}`", name))
+        };
+        let by = List::try_from(field("by")?).map_err(|_| anyhow!("Rule `by` is not a list"))?;
+        let keys = extract_keys(&by)?;
+
+        let method = field("method")?
+            .as_string_vector()
+            .ok_or_else(|| anyhow!("Rule `method` is not a string vector"))?;
+        let max_distance = field("max_distance")?
+            .as_real_vector()
+            .ok_or_else(|| anyhow!("Rule `max_distance` is not numeric"))?;
+        let integers = |name: &str| {
+            Vec::<Rint>::try_from(field(name)?)
+                .map_err(|_| anyhow!("Rule `{}` is not an integer vector", name))
+        };
+        let q = integers("q")?;
+        let max_prefix = integers("max_prefix")?;
+        let prefix_weight = Vec::<Rfloat>::try_from(field("prefix_weight")?)
+            .map_err(|_| anyhow!("Rule `prefix_weight` is not numeric"))?;
+
+        let join_methods = key_join_methods(
+            &keys,
+            &method,
+            &max_distance,
+            &q,
+            &max_prefix,
+            &prefix_weight,
+        )?;
+        Ok(StringRule {
+            keys,
+            labels: method.iter().map(|m| m.to_lowercase()).collect(),
+            join_methods,
+        })
+    }
+
+    /// Find the pairs satisfying every key, with one distance vector per key.
+    /// The most selective key generates candidates, within `blocks` if given,
+    /// and the others are checked pairwise.
+    pub fn matches(
+        &self,
+        df1: &List,
+        df2: &List,
+        blocks: Option<&[(Vec<usize>, Vec<usize>)]>,
+        pool: &SearchPool,
+    ) -> Result<KeyMatches> {
+        let order = if self.keys.len() > 1 {
+            key_order(&key_selectivity(
+                df1,
+                df2,
+                &self.keys,
+                &self.join_methods,
+                pool,
+            )?)
+        } else {
+            vec![0]
+        };
+        let (lk, rk) = &self.keys[order[0]];
+        let first_method = &self.join_methods[order[0]];
+        let mut matchdat = match blocks {
+            Some(blocks) => {
+                first_method.fuzzy_indices_blocked(df1, lk, df2, rk, blocks, None, pool)?
+            }
+            None => first_method.fuzzy_indices(df1, lk, df2, rk, None, pool)?,
+        };
+        matchdat.sort_unstable_by_key(|&(i, j, _)| (i, j));
+
+        let mut idxs1 = Vec::with_capacity(matchdat.len());
+        let mut idxs2 = Vec::with_capacity(matchdat.len());
+        let mut dists = Vec::with_capacity(matchdat.len());
+        for (i, j, d) in matchdat {
+            idxs1.push(i);
+            idxs2.push(j);
+            dists.push(d);
+        }
+        let mut dists = vec![dists];
+
+        for &k in &order[1..] {
+            (idxs1, idxs2, dists) = difference_pairs(
+                df1,
+                &idxs1,
+                df2,
+                &idxs2,
+                &self.keys[k],
+                &dists,
+                &self.join_methods[k],
+                pool,
+            )?
+        }
+        Ok((idxs1, idxs2, restore_order(dists, &order)))
+    }
+
+    /// Positions of the given pairs that satisfy every key.
+    pub fn keep_positions(
+        &self,
+        df1: &List,
+        idxs1: &[usize],
+        df2: &List,
+        idxs2: &[usize],
+        pool: &SearchPool,
+    ) -> Result<Vec<usize>> {
+        let mut keep: Vec<usize> = (0..idxs1.len()).collect();
+        for ((lk, rk), join_method) in self.keys.iter().zip(&self.join_methods) {
+            let rows1: Vec<usize> = keep.iter().map(|&p| idxs1[p]).collect();
+            let rows2: Vec<usize> = keep.iter().map(|&p| idxs2[p]).collect();
+
+            let vec1_binding = slice_key(df1, lk, &rows1, "df1")?;
+            let vec1: Vec<&str> = vec1_binding
+                .as_str_vector()
+                .ok_or_else(|| anyhow!("Failed to convert df1 column '{}' to string vector", lk))?;
+            let vec2_binding = slice_key(df2, rk, &rows2, "df2")?;
+            let vec2: Vec<&str> = vec2_binding
+                .as_str_vector()
+                .ok_or_else(|| anyhow!("Failed to convert df2 column '{}' to string vector", rk))?;
+
+            let (kept, _) = join_method.compare_pairs(&vec1, &vec2, pool)?;
+            keep = kept.iter().map(|&k| keep[k]).collect();
+        }
+        Ok(keep)
+    }
+}
@@ -1,38 +1,51 @@
-use crate::utils::robj_index_map;
+use crate::explain::busy_timer;
+use crate::progress::{install, SearchPool};
+use crate::string::topk::{MatchCollector, MatchSink};
 use anyhow::Result;
 use extendr_api::prelude::*;
-use itertools::iproduct;
 use rayon::prelude::*;
-use rayon::ThreadPool;
 use rustc_hash::FxHashMap;
 
 pub struct Soundex;
 impl Soundex {
-    pub fn fuzzy_indices(
+    pub fn fuzzy_indices<S: MatchSink>(
         &self,
-        df1: &List,
-        left_key: &str,
-        df2: &List,
-        right_key: &str,
-        pool: &ThreadPool,
-    ) -> Result<Vec<(usize, usize, f64)>> {
-        let map1 = robj_index_map(&df1, left_key)?;
-        let map2 = robj_index_map(&df2, right_key)?;
-
-        let idxs: Vec<(usize, usize, f64)> = pool.install(|| {
+        map1: &FxHashMap<&str, Vec<usize>>,
+        map2: &FxHashMap<&str, Vec<usize>>,
+        top_k: Option<usize>,
+        sink: &S,
+        pool: &SearchPool,
+    ) -> Result<Vec<S::Item>> {
+        install(pool, map1.len(), || {
             map1.par_iter()
-                .filter_map(|(k1, v1)| self.compare_one_to_many(k1, v1, &map2))
+                .filter(|_| pool.tick())
+                .filter_map(|(k1, v1)| {
+                    let _busy = busy_timer();
+                    let matches = self.compare_one_to_many(k1, map2, top_k)?;
+                    Some(sink.take(v1, matches))
+                })
                 .flatten()
                 .collect()
-        });
-        Ok(idxs)
+        })
+    }
+
+    /// Whether two names share a Soundex code, including the alternate code
+    /// used for names with prefixes.
+    pub fn codes_match(&self, l: &str, r: &str) -> bool {
+        let (sx_l, alt_l) = soundex_na_dual(l);
+        let (sx_r, alt_r) = soundex_na_dual(r);
+
+        sx_l == sx_r
+            || alt_l == Some(sx_r)
+            || alt_r == Some(sx_l)
+            || (alt_l.is_some() && alt_r.is_some() && alt_l == alt_r)
     }
 
     pub fn compare_pairs(
         &self,
         left: &Vec<&str>,
         right: &Vec<&str>,
-        pool: &ThreadPool,
+        pool: &SearchPool,
     ) -> Result<(Vec<usize>, Vec<f64>)> {
         let out = pool.install(|| {
             left.par_iter()
@@ -43,14 +56,7 @@ impl Soundex {
                         return None;
                     }
 
-                    let (sx_l, alt_l) = soundex_na_dual(l);
-                    let (sx_r, alt_r) = soundex_na_dual(r);
-
-                    if sx_l == sx_r
-                        || alt_l == Some(sx_r)
-                        || alt_r == Some(sx_l)
-                        || (alt_l.is_some() && alt_r.is_some() && alt_l == alt_r)
-                    {
+                    if self.codes_match(l, r) {
                         Some((i, 0.))
                     } else {
                         None
@@ -64,14 +70,14 @@ impl Soundex {
     fn compare_one_to_many(
         &self,
         k1: &str,
-        v1: &Vec<usize>,
         idx_map: &FxHashMap<&str, Vec<usize>>,
-    ) -> Option<Vec<(usize, usize, f64)>> {
+        top_k: Option<usize>,
+    ) -> Option<MatchCollector> {
         if k1.is_na() {
             return None;
         }
 
-        let mut idxs: Vec<(usize, usize, f64)> = Vec::new();
+        let mut matches = MatchCollector::new(0., top_k);
         let (sx1, alt1) = soundex_na_dual(k1);
         let sx1 = sx1.as_str();
 
@@ -87,17 +93,11 @@ impl Soundex {
                 || alt2 == Some(sx1.to_string())
                 || (alt1.is_some() && alt2.is_some() && alt1 == alt2)
             {
-                iproduct!(v1, v2).for_each(|(a, b)| {
-                    idxs.push((*a, *b, 0.));
-                });
+                matches.push(v2, 0.);
             }
         }
 
-        if idxs.is_empty() {
-            None
-        } else {
-            Some(idxs)
-        }
+        Some(matches)
     }
 }
 
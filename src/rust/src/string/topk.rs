@@ -0,0 +1,182 @@
+use crate::progress::SearchPool;
+use anyhow::{anyhow, Result};
+use itertools::iproduct;
+use ordered_float::OrderedFloat;
+use rayon::iter::IntoParallelIterator;
+use rustc_hash::FxHashMap;
+use std::collections::BinaryHeap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TopKBy {
+    Left,
+    Right,
+    Both,
+}
+
+impl TopKBy {
+    pub fn new(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "left" => Ok(TopKBy::Left),
+            "right" => Ok(TopKBy::Right),
+            "both" => Ok(TopKBy::Both),
+            _ => Err(anyhow!("Unknown `top_k_by` value: {}", s)),
+        }
+    }
+}
+
+/// Collects the matches of a single left-hand key.
+///
+/// Without a `k`, every right-hand row within `max_distance` is kept. With a
+/// `k`, only the `k` best right-hand rows are kept in a bounded max-heap, and
+/// `threshold()` shrinks to the distance of the current worst kept match once
+/// the heap is full, so callers can skip comparisons that can no longer win.
+/// Ties are broken by the lower right-hand row number.
+pub enum MatchCollector {
+    All {
+        max_distance: f64,
+        matches: Vec<(usize, f64)>,
+    },
+    TopK {
+        max_distance: f64,
+        k: usize,
+        heap: BinaryHeap<(OrderedFloat<f64>, usize)>,
+    },
+}
+
+impl MatchCollector {
+    pub fn new(max_distance: f64, top_k: Option<usize>) -> Self {
+        match top_k {
+            None => MatchCollector::All {
+                max_distance,
+                matches: Vec::new(),
+            },
+            Some(k) => MatchCollector::TopK {
+                max_distance,
+                k,
+                heap: BinaryHeap::with_capacity(k + 1),
+            },
+        }
+    }
+
+    /// Current cutoff: a candidate further away than this cannot be kept.
+    pub fn threshold(&self) -> f64 {
+        match self {
+            MatchCollector::All { max_distance, .. } => *max_distance,
+            MatchCollector::TopK {
+                max_distance,
+                k,
+                heap,
+            } => {
+                if heap.len() < *k {
+                    *max_distance
+                } else {
+                    heap.peek().map(|(d, _)| d.0).unwrap_or(*max_distance)
+                }
+            }
+        }
+    }
+
+    pub fn push(&mut self, v2: &[usize], dist: f64) {
+        match self {
+            MatchCollector::All { matches, .. } => {
+                matches.extend(v2.iter().map(|&j| (j, dist)));
+            }
+            MatchCollector::TopK { k, heap, .. } => {
+                for &j in v2 {
+                    let item = (OrderedFloat(dist), j);
+                    if heap.len() < *k {
+                        heap.push(item);
+                    } else if heap.peek().is_some_and(|worst| item < *worst) {
+                        heap.pop();
+                        heap.push(item);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The collected right-hand rows and their distances, closest first
+    /// with a `k`.
+    pub fn into_matches(self) -> Vec<(usize, f64)> {
+        match self {
+            MatchCollector::All { matches, .. } => matches,
+            MatchCollector::TopK { heap, .. } => heap
+                .into_sorted_vec()
+                .into_iter()
+                .map(|(d, j)| (j, d.0))
+                .collect(),
+        }
+    }
+
+    /// Expand the collected right-hand rows against all left-hand rows
+    /// sharing this key.
+    pub fn finish<'a>(self, v1: impl IntoIterator<Item = &'a usize>) -> Vec<(usize, usize, f64)> {
+        iproduct!(v1, self.into_matches())
+            .map(|(&i, (j, d))| (i, j, d))
+            .collect()
+    }
+}
+
+/// What a search makes of the matches of each left-hand key, given its
+/// left-hand rows, as soon as they are collected.
+pub trait MatchSink: Sync {
+    type Item: Send;
+    type Out: IntoParallelIterator<Item = Self::Item> + Send;
+
+    fn take(&self, v1: &[usize], matches: MatchCollector) -> Self::Out;
+}
+
+/// Expands the matches of each key into row pairs, counted towards
+/// `fozzie.max_matches`.
+pub struct RowPairs<'a>(pub &'a SearchPool);
+
+impl MatchSink for RowPairs<'_> {
+    type Item = (usize, usize, f64);
+    type Out = Vec<(usize, usize, f64)>;
+
+    fn take(&self, v1: &[usize], matches: MatchCollector) -> Self::Out {
+        let pairs = matches.finish(v1);
+        self.0.found(pairs.len());
+        pairs
+    }
+}
+
+/// Positions of the pairs that rank within the `k` best matches of their
+/// left row, right row, or both. Pairs are ranked by `scores`, with ties
+/// broken by the row number on the opposite side.
+pub fn top_k_positions(
+    idxs1: &[usize],
+    idxs2: &[usize],
+    scores: &[f64],
+    k: usize,
+    by: TopKBy,
+) -> Vec<usize> {
+    let ranked = |group_on: &[usize], tie_on: &[usize]| -> Vec<bool> {
+        let mut groups: FxHashMap<usize, Vec<usize>> = FxHashMap::default();
+        for (pos, &key) in group_on.iter().enumerate() {
+            groups.entry(key).or_default().push(pos);
+        }
+
+        let mut keep = vec![false; group_on.len()];
+        for (_, mut positions) in groups {
+            positions.sort_by_key(|&p| (OrderedFloat(scores[p]), tie_on[p]));
+            positions.into_iter().take(k).for_each(|p| keep[p] = true);
+        }
+        keep
+    };
+
+    let keep: Vec<bool> = match by {
+        TopKBy::Left => ranked(idxs1, idxs2),
+        TopKBy::Right => ranked(idxs2, idxs1),
+        TopKBy::Both => ranked(idxs1, idxs2)
+            .into_iter()
+            .zip(ranked(idxs2, idxs1))
+            .map(|(a, b)| a && b)
+            .collect(),
+    };
+
+    keep.iter()
+        .enumerate()
+        .filter_map(|(pos, &k)| if k { Some(pos) } else { None })
+        .collect()
+}
@@ -1,6 +1,6 @@
+use crate::progress::SearchPool;
 use anyhow::{anyhow, Result};
 use extendr_api::prelude::*;
-use rayon::ThreadPool;
 use rayon::ThreadPoolBuilder;
 use rustc_hash::FxHashMap;
 
@@ -19,6 +19,42 @@ pub fn robj_index_map<'a>(df: &'a List, key: &'a str) -> Result<FxHashMap<&'a st
     Ok(map)
 }
 
+/// Index a subset of rows of a string column by value. `rows` are 1-based,
+/// and the returned indices refer to rows of the full column.
+pub fn subset_index_map<'a>(vals: &[&'a str], rows: &[usize]) -> FxHashMap<&'a str, Vec<usize>> {
+    let mut map: FxHashMap<&str, Vec<usize>> = FxHashMap::default();
+    for &row in rows {
+        map.entry(vals[row - 1]).or_default().push(row);
+    }
+    map
+}
+
+/// Group rows into blocks by exact key. Block codes are shared by both sides,
+/// with `NA` for rows that belong to no block. Returns the 1-based rows of
+/// each block that has rows on both sides.
+pub fn block_rows(block1: &[Rint], block2: &[Rint]) -> Vec<(Vec<usize>, Vec<usize>)> {
+    let mut blocks: FxHashMap<i32, (Vec<usize>, Vec<usize>)> = FxHashMap::default();
+    for (i, code) in block1.iter().enumerate() {
+        if !code.is_na() {
+            blocks.entry(code.inner()).or_default().0.push(i + 1);
+        }
+    }
+    for (j, code) in block2.iter().enumerate() {
+        if !code.is_na() {
+            if let Some(block) = blocks.get_mut(&code.inner()) {
+                block.1.push(j + 1);
+            }
+        }
+    }
+
+    let mut blocks: Vec<_> = blocks
+        .into_iter()
+        .filter(|(_, (_, rows2))| !rows2.is_empty())
+        .collect();
+    blocks.sort_unstable_by_key(|(code, _)| *code);
+    blocks.into_iter().map(|(_, rows)| rows).collect()
+}
+
 pub fn transpose_map_fx(
     data: FxHashMap<(usize, usize), Vec<f64>>,
 ) -> (Vec<usize>, Vec<usize>, Vec<Vec<f64>>) {
@@ -53,29 +89,14 @@ pub fn transpose_map_fx(
     (keys1, keys2, transposed_values)
 }
 
-pub fn strvec_to_qgram_map<'a>(
-    df: &'a List,
-    key: &'a str,
+/// Attach the q-gram profile of each distinct string to its row indices.
+pub fn qgram_index_map<'a>(
+    map: &FxHashMap<&'a str, Vec<usize>>,
     q: usize,
-) -> Result<FxHashMap<&'a str, (FxHashMap<&'a str, usize>, Vec<usize>)>> {
-    let mut qgram_map: FxHashMap<&'a str, (FxHashMap<&'a str, usize>, Vec<usize>)> =
-        FxHashMap::default();
-
-    let str_iter = df
-        .dollar(key)
-        .map_err(|_| anyhow!("Column '{}' not found in dataframe", key))?
-        .as_str_iter()
-        .ok_or_else(|| anyhow!("Column '{}' is not a string vector", key))?;
-
-    for (index, val) in str_iter.enumerate() {
-        let hm: FxHashMap<&str, usize> = get_qgrams(val, q);
-        qgram_map
-            .entry(val)
-            .and_modify(|v| v.1.push(index + 1))
-            .or_insert((hm, vec![index + 1]));
-    }
-
-    Ok(qgram_map)
+) -> FxHashMap<&'a str, (FxHashMap<&'a str, usize>, Vec<usize>)> {
+    map.iter()
+        .map(|(&val, idxs)| (val, (get_qgrams(val, q), idxs.clone())))
+        .collect()
 }
 
 pub fn get_qgrams(s: &str, q: usize) -> FxHashMap<&str, usize> {
@@ -98,18 +119,19 @@ pub fn get_qgrams(s: &str, q: usize) -> FxHashMap<&str, usize> {
     qgram_map
 }
 
-pub fn get_pool(nthread: Option<usize>) -> Result<ThreadPool> {
+/// Thread pool of one join call, with `nthread` threads or Rayon's default.
+pub fn get_pool(nthread: Option<usize>) -> Result<SearchPool> {
     if let Some(nt) = nthread {
         let pool = ThreadPoolBuilder::new()
             .num_threads(nt)
             .build()
             .map_err(|e| anyhow!("{e}"))?;
-        Ok(pool)
+        Ok(SearchPool::new(pool))
     } else {
         let pool = rayon::ThreadPoolBuilder::new()
             .build()
             .map_err(|e| anyhow!("{e}"))?;
-        Ok(pool)
+        Ok(SearchPool::new(pool))
     }
 }
 
@@ -131,3 +153,28 @@ pub fn any_numeric_to_vec64(df: &List, key: &str) -> Result<Vec<f64>> {
 
     Ok(df_vals)
 }
+
+/// Total distance of each matched pair, summed across join keys.
+pub fn summed_distances(dists: &[Vec<f64>]) -> Vec<f64> {
+    let n = dists.first().map_or(0, |col| col.len());
+    (0..n)
+        .map(|row| dists.iter().map(|col| col[row]).sum())
+        .collect()
+}
+
+/// Subset matched pairs and their distances to the given positions.
+pub fn subset_pairs(
+    keep: &[usize],
+    idxs1: &[usize],
+    idxs2: &[usize],
+    dists: &[Vec<f64>],
+) -> (Vec<usize>, Vec<usize>, Vec<Vec<f64>>) {
+    (
+        keep.iter().map(|&p| idxs1[p]).collect(),
+        keep.iter().map(|&p| idxs2[p]).collect(),
+        dists
+            .iter()
+            .map(|col| keep.iter().map(|&p| col[p]).collect())
+            .collect(),
+    )
+}